@@ -0,0 +1,149 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::Config,
+    dist,
+    error::Error,
+    front_matter,
+    parser::{self, MarkdownNodes, ParseDiagnostic},
+    translate,
+};
+
+/// Options that change how pages are built.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuildOptions {
+    /// Print the parsed nodes of every page.
+    pub verbose: bool,
+    /// Inject the live reload client into every page, used by `lssg serve`.
+    pub live_reload: bool,
+}
+
+/// How many of the task list items on a page are checked.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskCount {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl TaskCount {
+    fn count(nodes: &[MarkdownNodes]) -> Self {
+        let mut count = TaskCount::default();
+        for node in nodes {
+            match node {
+                MarkdownNodes::List(list) => {
+                    for item in &list.items {
+                        if let Some(checked) = item.checked {
+                            count.total += 1;
+                            count.done += usize::from(checked);
+                        }
+                        count.add(TaskCount::count(&item.children));
+                    }
+                }
+                MarkdownNodes::BlockQuote(quote) => count.add(TaskCount::count(&quote.children)),
+                _ => {}
+            }
+        }
+        count
+    }
+
+    fn add(&mut self, other: TaskCount) {
+        self.done += other.done;
+        self.total += other.total;
+    }
+}
+
+/// The result of building a single page.
+pub enum PageOutcome {
+    /// The page was written to this path, relative to `dist/`, with
+    /// warnings about content that renders differently than it reads.
+    Written(PathBuf, TaskCount, Vec<ParseDiagnostic>),
+    /// The page is marked as a draft and was skipped.
+    Draft,
+}
+
+/// Runs the parse, translate and write pipeline for a single content file,
+/// writing the page under `dist_dir`.
+pub fn build_page(
+    current_dir: &Path,
+    dist_dir: &Path,
+    target: &Path,
+    config: &Config,
+    options: BuildOptions,
+) -> Result<PageOutcome, Error> {
+    let content = read_to_string(target).map_err(|err| Error::io(target, err))?;
+
+    let (front_matter, body) =
+        front_matter::split_front_matter(&content).map_err(|message| Error::Parse {
+            path: target.to_path_buf(),
+            message,
+        })?;
+
+    if front_matter.draft {
+        return Ok(PageOutcome::Draft);
+    }
+
+    let start = front_matter::body_start(&content, body);
+    let parser = parser::MarkdownParser::new(body.to_string())
+        .starting_at(start)
+        .with_extensions(config.content.extensions);
+    let nodes = parser.parse();
+
+    if options.verbose {
+        for node in &nodes {
+            println!("{:?}", node);
+        }
+    }
+
+    let tasks = TaskCount::count(&nodes);
+    let mut warnings = Vec::new();
+    parser.check_footnotes(&nodes, &mut warnings);
+
+    let mut translator = translate::Translator::new(&nodes);
+    let translated = translator.translate("");
+
+    let output_file = dist::output_path(&current_dir.join("content"), target);
+
+    let render_error = |err: Error| Error::Render {
+        path: target.to_path_buf(),
+        message: format!(
+            "Failed to write `{}`: {}",
+            dist::display_path(current_dir, &dist_dir.join(&output_file)).display(),
+            err
+        ),
+    };
+
+    dist::create_dist(dist_dir).map_err(render_error)?;
+
+    dist::create_file(
+        dist_dir,
+        &output_file,
+        dist::create_dom(
+            translated.as_str(),
+            config,
+            &front_matter,
+            options.live_reload,
+        )
+        .as_str(),
+    )
+    .map_err(render_error)?;
+
+    Ok(PageOutcome::Written(output_file, tasks, warnings))
+}
+
+/// Formats a warning found while building `target` as a line of build
+/// output.
+pub fn format_warning(current_dir: &Path, target: &Path, warning: &ParseDiagnostic) -> String {
+    format!(
+        " {} `{}:{}:{}` {}",
+        "Warning".yellow().bold(),
+        target.strip_prefix(current_dir).unwrap_or(target).display(),
+        warning.span.start.line,
+        warning.span.start.column,
+        warning.message
+    )
+}
@@ -0,0 +1,191 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::{Config, find_target_files},
+    dist,
+    error::Error,
+    front_matter,
+    parser::{Extensions, MarkdownParser, Severity},
+    source::{Position, SourceSpan},
+};
+
+/// A diagnostic tied to a content file.
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub span: SourceSpan,
+    pub severity: Severity,
+    pub message: String,
+    /// The source line the span starts on, shown under the message.
+    pub snippet: Option<String>,
+}
+
+/// The outcome of checking every page of a site.
+pub struct Report {
+    pub files: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+}
+
+/// Parses every content file and collects diagnostics without writing
+/// anything to `dist/`.
+pub fn check_site(current_dir: &Path, config: &Config) -> Result<Report, Error> {
+    let content_dir = current_dir.join("content");
+    let targets = find_target_files(content_dir.clone(), "md")?;
+    let mut diagnostics = Vec::new();
+
+    for collision in dist::find_output_collisions(&content_dir, &targets) {
+        diagnostics.push(Diagnostic {
+            path: collision.second,
+            span: file_start(),
+            snippet: None,
+            severity: Severity::Error,
+            message: format!(
+                "would be written to `dist/{}`, which `{}` is also written to",
+                collision.output.display(),
+                collision
+                    .first
+                    .strip_prefix(current_dir)
+                    .unwrap_or(&collision.first)
+                    .display()
+            ),
+        });
+    }
+
+    for target in &targets {
+        diagnostics.extend(check_file(target, config.content.extensions));
+    }
+
+    // Report paths relative to the site, as they would be typed
+    for diagnostic in &mut diagnostics {
+        if let Ok(relative) = diagnostic.path.strip_prefix(current_dir) {
+            diagnostic.path = relative.to_path_buf();
+        }
+    }
+
+    Ok(Report {
+        files: targets.len(),
+        diagnostics,
+    })
+}
+
+/// A span pointing at the start of a file, for problems with the whole file.
+fn file_start() -> SourceSpan {
+    SourceSpan {
+        start: Position::START,
+        end: Position::START,
+    }
+}
+
+fn check_file(path: &Path, extensions: Extensions) -> Vec<Diagnostic> {
+    let diagnostic = |severity, message| Diagnostic {
+        path: path.to_path_buf(),
+        span: file_start(),
+        severity,
+        message,
+        snippet: None,
+    };
+
+    let content = match read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            let message = format!("failed to read file: {}", err);
+            return vec![diagnostic(Severity::Error, message)];
+        }
+    };
+
+    let (_, body) = match front_matter::split_front_matter(&content) {
+        Ok(split) => split,
+        Err(err) => return vec![diagnostic(Severity::Error, err)],
+    };
+
+    // Report positions in the file rather than in the body after front matter
+    let start = front_matter::body_start(&content, body);
+
+    MarkdownParser::new(body.to_string())
+        .starting_at(start)
+        .with_extensions(extensions)
+        .diagnose()
+        .into_iter()
+        .map(|d| Diagnostic {
+            snippet: content
+                .lines()
+                .nth(d.span.start.line - 1)
+                .map(str::to_string),
+            span: d.span,
+            ..diagnostic(d.severity, d.message)
+        })
+        .collect()
+}
+
+/// Prints a diagnostic in the style of rustc, with the offending source line
+/// and the span underlined below it.
+pub fn print_diagnostic(diagnostic: &Diagnostic) {
+    let highlight = |text: &str| match diagnostic.severity {
+        Severity::Error => text.red().bold().to_string(),
+        Severity::Warning => text.yellow().bold().to_string(),
+    };
+    let severity = match diagnostic.severity {
+        Severity::Error => highlight("error"),
+        Severity::Warning => highlight("warning"),
+    };
+
+    let SourceSpan { start, end } = diagnostic.span;
+    let gutter = " ".repeat(start.line.to_string().len());
+    let bar = "|".blue().bold().to_string();
+
+    eprintln!("{}: {}", severity, diagnostic.message.bold());
+    eprintln!(
+        "{}{} {}:{}:{}",
+        gutter,
+        "-->".blue().bold(),
+        diagnostic.path.display(),
+        start.line,
+        start.column
+    );
+
+    if let Some(snippet) = &diagnostic.snippet {
+        // Keep tabs in the padding so the markers line up with the source
+        let padding: String = snippet
+            .chars()
+            .take(start.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = match end.line == start.line {
+            true => end.column.saturating_sub(start.column),
+            false => snippet.chars().count().saturating_sub(start.column - 1),
+        };
+
+        eprintln!("{} {}", gutter, bar);
+        eprintln!("{} {} {}", start.line.blue().bold(), bar, snippet);
+        eprintln!(
+            "{} {} {}{}",
+            gutter,
+            bar,
+            padding,
+            highlight(&"^".repeat(width.max(1)))
+        );
+    }
+
+    eprintln!();
+}
@@ -1,9 +1,14 @@
 use std::fs;
-use std::{env::current_dir, path::PathBuf};
+use std::{
+    env::current_dir,
+    path::{Path, PathBuf},
+};
 
 use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 
+use crate::{error::Error, parser::Extensions};
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
@@ -13,12 +18,12 @@ pub struct Cli {
     pub verbose: bool,
 }
 
-pub fn get_current_directory() -> std::path::PathBuf {
-    current_dir().expect("Failed to get current directory")
+pub fn get_current_directory() -> Result<PathBuf, Error> {
+    current_dir().map_err(|err| Error::io(".", err))
 }
 
-pub fn get_config_file_path() -> std::path::PathBuf {
-    get_current_directory().join("config.toml")
+pub fn get_config_file_path(current_dir: &Path) -> PathBuf {
+    current_dir.join("config.toml")
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -32,39 +37,75 @@ pub struct Config {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Content {
     pub latex_enabled: bool,
+    #[serde(flatten)]
+    pub extensions: Extensions,
 }
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum Command {
-    Build,
+    /// Build every page in `content/` into `dist/`
+    Build {
+        /// Remove outputs of earlier builds whose sources no longer exist
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Remove every file lssg has written to `dist/`
+    Clean,
+    /// Parse every page and report problems without writing to `dist/`
+    Check {
+        /// Fail on warnings as well as errors
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+    /// Build the site, serve `dist/` locally and reload open pages on changes
+    Serve {
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+    },
+    /// Build the site, then rebuild changed pages until interrupted
+    Watch {
+        /// Poll for changes instead of using filesystem notifications
+        #[arg(long)]
+        poll: bool,
+    },
+    /// Create a new site skeleton in `dir`
+    Init { dir: PathBuf },
+    /// Create a new draft page at `path`, relative to `content/`
+    New { path: PathBuf },
 }
 
-pub fn get_config() -> Result<Config, String> {
-    let config_path = get_config_file_path();
+pub fn get_config(current_dir: &Path) -> Result<Config, Error> {
+    let config_path = get_config_file_path(current_dir);
 
-    let config_str = fs::read_to_string(config_path)
-        .map_err(|err| format!("Failed to read config file: {}", err))?;
+    let config_str = fs::read_to_string(&config_path).map_err(|err| Error::Config {
+        path: config_path.clone(),
+        message: format!("Failed to read config file: {}", err),
+    })?;
 
-    let config: Config = toml::from_str(&config_str)
-        .map_err(|err| format!("Failed to parse config file: {}", err))?;
+    let config: Config = toml::from_str(&config_str).map_err(|err| Error::Config {
+        path: config_path.clone(),
+        message: format!("Failed to parse config file: {}", err),
+    })?;
 
     Ok(config)
 }
 
-pub fn find_target_files(dir: PathBuf, extension: &str) -> Vec<PathBuf> {
+pub fn find_target_files(dir: PathBuf, extension: &str) -> Result<Vec<PathBuf>, Error> {
     if dir.is_dir() {
         let mut files = Vec::new();
-        for entry in fs::read_dir(dir).expect("Failed to read directory") {
-            let entry = entry.expect("Failed to read directory entry");
+        let entries = fs::read_dir(&dir).map_err(|err| Error::io(&dir, err))?;
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::io(&dir, err))?;
             let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == extension) {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == extension) {
                 files.push(path);
             } else if path.is_dir() {
-                files.extend(find_target_files(path, extension));
+                files.extend(find_target_files(path, extension)?);
             }
         }
-        files
+        files.sort();
+        Ok(files)
     } else {
-        Vec::new()
+        Ok(Vec::new())
     }
 }
@@ -0,0 +1,208 @@
+//! Runs the examples of the CommonMark spec through the parser and translator.
+//!
+//! The spec is vendored in `tests/commonmark/`, next to a list of the examples
+//! that are known to fail. The test fails when an example regresses, and also
+//! when a known failure starts passing, so the list can only shrink. Run with
+//! `UPDATE_KNOWN_FAILURES=1` to rewrite the list after fixing examples, and
+//! with `--nocapture` to see the pass rate of every section.
+
+use std::{collections::BTreeSet, env, fs, panic, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    parser::{Extensions, MarkdownParser},
+    translate::Translator,
+};
+
+const SPEC_VERSION: &str = "0.31.2";
+const SPEC: &str = include_str!("../tests/commonmark/spec-0.31.2.json");
+
+#[derive(Deserialize)]
+struct Example {
+    markdown: String,
+    html: String,
+    example: u32,
+    section: String,
+}
+
+fn known_failures_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/commonmark/known_failures.txt")
+}
+
+/// Reads the example numbers from the known failures list, one per line with
+/// anything after a `#` being a comment.
+fn read_known_failures() -> BTreeSet<u32> {
+    fs::read_to_string(known_failures_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split('#').next()?.trim().parse().ok())
+        .collect()
+}
+
+fn write_known_failures(examples: &[Example], failures: &BTreeSet<u32>) {
+    let mut contents = format!(
+        "# CommonMark {} spec examples that lssg is known to render differently.\n\
+         # Regenerate with `UPDATE_KNOWN_FAILURES=1 cargo test commonmark_spec`.\n",
+        SPEC_VERSION
+    );
+    for example in examples.iter().filter(|e| failures.contains(&e.example)) {
+        contents.push_str(&format!("{} # {}\n", example.example, example.section));
+    }
+
+    fs::write(known_failures_path(), contents).expect("failed to write known failures");
+}
+
+/// Renders an example as plain CommonMark, treating a panic as a failure
+/// rather than aborting the whole suite.
+fn render(markdown: &str) -> Option<String> {
+    let extensions = Extensions {
+        strikethrough: false,
+        highlight: false,
+        superscript: false,
+        subscript: false,
+    };
+
+    panic::catch_unwind(|| {
+        let nodes = MarkdownParser::new(markdown.to_string())
+            .with_extensions(extensions)
+            .parse();
+        Translator::new(&nodes).translate("")
+    })
+    .ok()
+}
+
+/// Whether a newline next to `tag` is insignificant, as it is for block tags
+/// and line breaks.
+fn is_newline_insensitive(tag: &str) -> bool {
+    let name: String = tag
+        .trim_start_matches(['<', '/'])
+        .chars()
+        .take_while(char::is_ascii_alphanumeric)
+        .collect();
+
+    matches!(
+        name.as_str(),
+        "p" | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "ul"
+            | "ol"
+            | "li"
+            | "blockquote"
+            | "pre"
+            | "hr"
+            | "br"
+            | "table"
+            | "thead"
+            | "tbody"
+            | "tr"
+            | "th"
+            | "td"
+    )
+}
+
+/// Normalizes HTML so that output which only differs from the spec in ways
+/// that don't change how it renders still matches: the empty `class`
+/// attributes lssg adds, self-closing slashes, escaped apostrophes and the
+/// newlines the spec puts between blocks and after line breaks.
+fn normalize(html: &str) -> String {
+    let html = html
+        .replace(" class=\"\"", "")
+        .replace(" class=\"language- \"", "")
+        .replace(" \">", "\">")
+        .replace(" />", ">")
+        .replace("&#39;", "'");
+
+    let mut normalized = String::with_capacity(html.len());
+    for (i, c) in html.char_indices() {
+        if c == '\n' {
+            let after_block = normalized.ends_with('>')
+                && normalized
+                    .rfind('<')
+                    .is_some_and(|start| is_newline_insensitive(&normalized[start..]));
+            let before_block =
+                html[i + 1..].starts_with('<') && is_newline_insensitive(&html[i + 1..]);
+            if after_block || before_block {
+                continue;
+            }
+        }
+        normalized.push(c);
+    }
+
+    normalized.trim_matches('\n').to_string()
+}
+
+#[test]
+fn commonmark_spec() {
+    let examples: Vec<Example> = serde_json::from_str(SPEC).expect("vendored spec is valid JSON");
+
+    let mut failures = BTreeSet::new();
+    // Passed and total examples of each section, in the order of the spec
+    let mut sections: Vec<(&str, usize, usize)> = Vec::new();
+
+    for example in &examples {
+        let passed = render(&example.markdown)
+            .is_some_and(|html| normalize(&html) == normalize(&example.html));
+        if !passed {
+            failures.insert(example.example);
+        }
+
+        let index = match sections
+            .iter()
+            .position(|(name, ..)| *name == example.section)
+        {
+            Some(index) => index,
+            None => {
+                sections.push((&example.section, 0, 0));
+                sections.len() - 1
+            }
+        };
+        sections[index].1 += usize::from(passed);
+        sections[index].2 += 1;
+    }
+
+    let passed = examples.len() - failures.len();
+    println!(
+        "CommonMark {}: {}/{} examples pass ({:.1}%)",
+        SPEC_VERSION,
+        passed,
+        examples.len(),
+        passed as f64 * 100.0 / examples.len() as f64
+    );
+    for (name, passed, total) in &sections {
+        println!(
+            "  {:<42} {:>3}/{:<3} {:>5.1}%",
+            name,
+            passed,
+            total,
+            *passed as f64 * 100.0 / *total as f64
+        );
+    }
+
+    if env::var_os("UPDATE_KNOWN_FAILURES").is_some() {
+        write_known_failures(&examples, &failures);
+        return;
+    }
+
+    let known_failures = read_known_failures();
+    let regressed: Vec<_> = failures.difference(&known_failures).collect();
+    let fixed: Vec<_> = known_failures.difference(&failures).collect();
+
+    assert!(
+        regressed.is_empty(),
+        "examples {:?} no longer pass, see https://spec.commonmark.org/{}/#example-{}",
+        regressed,
+        SPEC_VERSION,
+        regressed[0]
+    );
+    assert!(
+        fixed.is_empty(),
+        "examples {:?} now pass, remove them from tests/commonmark/known_failures.txt \
+         or rerun with UPDATE_KNOWN_FAILURES=1",
+        fixed
+    );
+}
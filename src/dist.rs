@@ -1,33 +1,201 @@
-use std::{fs, path::PathBuf, process};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Component, Path, PathBuf},
+};
 
-use owo_colors::OwoColorize;
+use crate::{cli::Config, error::Error, front_matter::FrontMatter, translate::escape_html};
 
-use crate::cli::Config;
+/// The directory `lssg build` writes the site to.
+pub fn dist_dir(current_dir: &Path) -> PathBuf {
+    current_dir.join("dist")
+}
+
+/// Shortens a path under the site directory for messages, so pages in `dist/`
+/// read as `dist/index.html` while other output directories stay absolute.
+pub fn display_path<'a>(current_dir: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(current_dir).unwrap_or(path)
+}
 
-pub fn create_dist(current_dir: &PathBuf) {
+pub fn create_dist(dist_dir: &Path) -> Result<(), Error> {
     // Create the distribution directory if it doesn't exist
-    if let Err(err) = fs::create_dir_all(current_dir.join("dist")) {
-        println!("Error creating distribution directory: {}", err);
-        process::exit(1);
+    fs::create_dir_all(dist_dir).map_err(|err| Error::io(dist_dir, err))
+}
+
+pub fn create_file(dist_dir: &Path, output_file: &Path, translated: &str) -> Result<(), Error> {
+    let output_path = dist_dir.join(output_file);
+
+    // Create any subdirectories mirrored from the content directory
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
     }
+
+    fs::write(&output_path, translated).map_err(|err| Error::io(output_path, err))
 }
 
-pub fn create_file(current_dir: &PathBuf, output_file: &str, translated: &str) {
-    let output_dir = current_dir.join("dist");
-    let result = fs::write(output_dir.join(output_file), translated).map_err(|e| {
-        format!(
-            "{} Failed to write translated content: {}",
-            "Error".bold().red(),
-            e
-        )
-    });
+/// File in `dist/` listing every output lssg has written there, so stale pages
+/// can be removed without touching files lssg did not create.
+const MANIFEST_FILE: &str = ".lssg-manifest";
+
+/// Reads the outputs recorded by previous builds, relative to `dist/`.
+pub fn read_manifest(dist_dir: &Path) -> BTreeSet<PathBuf> {
+    let manifest = dist_dir.join(MANIFEST_FILE);
+
+    fs::read_to_string(manifest)
+        .map(|contents| {
+            contents
+                .lines()
+                .map(PathBuf::from)
+                // Never trust entries that could point outside of `dist/`
+                .filter(|path| {
+                    path.components().next().is_some()
+                        && path.components().all(|c| matches!(c, Component::Normal(_)))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn write_manifest(dist_dir: &Path, outputs: &BTreeSet<PathBuf>) -> Result<(), Error> {
+    let manifest = dist_dir.join(MANIFEST_FILE);
+
+    if outputs.is_empty() {
+        return match fs::remove_file(&manifest) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::io(manifest, err)),
+            _ => Ok(()),
+        };
+    }
+
+    let contents = outputs
+        .iter()
+        .map(|path| format!("{}\n", path.to_string_lossy()))
+        .collect::<String>();
+
+    fs::write(&manifest, contents).map_err(|err| Error::io(manifest, err))
+}
+
+/// Applies `update` to the recorded outputs and writes them back.
+pub fn update_manifest(
+    dist_dir: &Path,
+    update: impl FnOnce(&mut BTreeSet<PathBuf>),
+) -> Result<(), Error> {
+    let mut outputs = read_manifest(dist_dir);
+    update(&mut outputs);
+    write_manifest(dist_dir, &outputs)
+}
+
+/// Removes an output file, along with any directories under `dist/` that are
+/// left empty. Returns whether the file existed.
+pub fn remove_output(dist_dir: &Path, output: &Path) -> bool {
+    let path = dist_dir.join(output);
+
+    if fs::remove_file(&path).is_err() {
+        return false;
+    }
+
+    for dir in path.ancestors().skip(1) {
+        if dir == dist_dir || fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
 
-    if let Err(err) = result {
-        println!("{}", err);
+    true
+}
+
+/// Removes outputs a previous build wrote that are not in `current`, returning
+/// the paths that were removed.
+pub fn prune_outputs(
+    dist_dir: &Path,
+    previous: &BTreeSet<PathBuf>,
+    current: &BTreeSet<PathBuf>,
+) -> Vec<PathBuf> {
+    previous
+        .difference(current)
+        .filter(|output| remove_output(dist_dir, output))
+        .cloned()
+        .collect()
+}
+
+/// Records the outputs of a finished build in the manifest. With `prune`,
+/// outputs of previous builds that this one didn't write are removed and
+/// returned, otherwise they stay recorded so a later prune can remove them.
+pub fn record_build(
+    dist_dir: &Path,
+    outputs: BTreeSet<PathBuf>,
+    prune: bool,
+    failures: &[Error],
+) -> Result<Vec<PathBuf>, Error> {
+    let previous = read_manifest(dist_dir);
+
+    // Never prune after a failed build, the outputs of broken pages are still
+    // wanted once they are fixed
+    let (manifest, removed) = match prune && failures.is_empty() {
+        true => {
+            let removed = prune_outputs(dist_dir, &previous, &outputs);
+            (outputs, removed)
+        }
+        false => (&outputs | &previous, Vec::new()),
+    };
+
+    write_manifest(dist_dir, &manifest)?;
+    Ok(removed)
+}
+
+/// Removes every output recorded in the manifest, then the manifest itself and
+/// `dist/` if nothing else is left in it.
+pub fn clean(dist_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let removed = prune_outputs(dist_dir, &read_manifest(dist_dir), &BTreeSet::new());
+    write_manifest(dist_dir, &BTreeSet::new())?;
+
+    // Only succeeds if the directory is empty, so unrelated files are kept
+    let _ = fs::remove_dir(dist_dir);
+
+    Ok(removed)
+}
+
+/// Maps a source file to its output path relative to `dist/`, mirroring its
+/// location under `content_dir`.
+pub fn output_path(content_dir: &Path, target: &Path) -> PathBuf {
+    target
+        .strip_prefix(content_dir)
+        .unwrap_or(target)
+        .with_extension("html")
+}
+
+/// An output path that more than one source file maps to.
+#[derive(Debug)]
+pub struct OutputCollision {
+    pub output: PathBuf,
+    pub first: PathBuf,
+    pub second: PathBuf,
+}
+
+/// Finds sources that would be written to the same output file. Paths are
+/// compared case-insensitively, since `dist/` is often deployed to filesystems
+/// that don't distinguish `Intro.html` from `intro.html`.
+pub fn find_output_collisions(content_dir: &Path, targets: &[PathBuf]) -> Vec<OutputCollision> {
+    let mut outputs: HashMap<String, &PathBuf> = HashMap::new();
+    let mut collisions = Vec::new();
+
+    for target in targets {
+        let output = output_path(content_dir, target);
+        let key = output.to_string_lossy().to_lowercase();
+
+        if let Some(first) = outputs.get(&key) {
+            collisions.push(OutputCollision {
+                output,
+                first: first.to_path_buf(),
+                second: target.clone(),
+            });
+        } else {
+            outputs.insert(key, target);
+        }
     }
+
+    collisions
 }
 
-pub fn get_headers(config: &Config) -> String {
+pub fn get_headers(config: &Config, front_matter: &FrontMatter) -> String {
     format!(
         r#"
     <meta charset="UTF-8">
@@ -38,8 +206,13 @@ pub fn get_headers(config: &Config) -> String {
     <script src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.11.1/highlight.min.js"></script>
     {}
     "#,
-        config.title,
-        config.description,
+        escape_html(front_matter.title.as_ref().unwrap_or(&config.title)),
+        escape_html(
+            front_matter
+                .description
+                .as_ref()
+                .unwrap_or(&config.description)
+        ),
         if config.content.latex_enabled {
             "<script id=\"MathJax-script\" async src=\"https://cdn.jsdelivr.net/npm/mathjax@3/es5/tex-mml-chtml.js\"></script>"
         } else {
@@ -48,9 +221,25 @@ pub fn get_headers(config: &Config) -> String {
     )
 }
 
-pub fn create_dom(translated: &str, config: &Config) -> String {
-    let headers = get_headers(config);
+/// Path of the server-sent events endpoint used to trigger live reloads.
+pub const LIVE_RELOAD_PATH: &str = "/__lssg/reload";
+
+pub fn create_dom(
+    translated: &str,
+    config: &Config,
+    front_matter: &FrontMatter,
+    live_reload: bool,
+) -> String {
+    let headers = get_headers(config, front_matter);
     let styles = css_styles(config);
+    let live_reload_script = if live_reload {
+        format!(
+            "<script>new EventSource(\"{}\").onmessage = () => location.reload();</script>",
+            LIVE_RELOAD_PATH
+        )
+    } else {
+        String::new()
+    };
 
     let html = format!(
         r#"
@@ -62,13 +251,14 @@ pub fn create_dom(translated: &str, config: &Config) -> String {
         <body>
             <div id="content">{}</div>
             <script>hljs.highlightAll();</script>
+            {}
         </body>
         <style>
             {}
         </style>
         </html>
         "#,
-        headers, translated, styles
+        headers, translated, live_reload_script, styles
     );
 
     html
@@ -77,9 +267,8 @@ pub fn create_dom(translated: &str, config: &Config) -> String {
 pub fn css_styles(config: &Config) -> String {
     match config.theme.as_str() {
         "frappe" => {
-            format!(
-                r#"
-                    :root {{
+            r#"
+                    :root {
                         --base: #303446;
                         --mantle: #292c3c;
                         --crust: #232634;
@@ -93,18 +282,19 @@ pub fn css_styles(config: &Config) -> String {
                         --subtext-1: #b5bfe2;
                         --text: #c6d0f5;
                         --red: #e78284;
-                    }}
+                        --yellow: #e5c890;
+                    }
 
-                    html, body {{
+                    html, body {
                         margin: 0;
                         padding: 0;
                         font-family: Ubuntu, sans-serif;
                         background-color: var(--base);
                         color: var(--text);
                         overflow-x: hidden;
-                    }}
+                    }
 
-                    #content {{
+                    #content {
                         margin-left: 2rem;
                         margin-right: 2rem;
                         padding: 0;
@@ -112,54 +302,285 @@ pub fn css_styles(config: &Config) -> String {
                         display: flex;
                         flex-direction: column;
 
-                    }}
+                    }
 
-                    pre {{
+                    pre {
                         width: fit-content;
                         border-radius: 0.5rem;
                         padding: 1rem;
                         display: block;
                         background-color: var(--crust) !important;
-                    }}
+                    }
 
-                    code {{
+                    code {
                         width: fit-content;
                         background-color: var(--crust) !important;
-                    }}
+                    }
 
-                    hr {{
+                    mark {
+                        background-color: var(--yellow);
+                        color: var(--crust);
+                    }
+
+                    li:has(> input[type="checkbox"]) {
+                        list-style: none;
+                    }
+
+                    hr {
                         border: none;
                         border-top: 1px solid var(--overlay-1);
                         margin: 1rem 0;
-                    }}
+                    }
+
+                    table {
+                        border-collapse: collapse;
+                        margin: 1rem 0;
+                    }
 
-                    img {{
+                    th, td {
+                        border: 1px solid var(--surface-1);
+                        padding: 0.25rem 0.75rem;
+                    }
+
+                    th {
+                        background-color: var(--mantle);
+                    }
+
+                    img {
                         max-width: fit-content;
                         height: auto;
-                    }}
+                    }
 
-                    video {{
+                    video {
                         max-width: 100%;
                         height: auto;
-                    }}
+                    }
 
-                    a {{
+                    a {
                         color: var(--text);
                         text-decoration: none;
-                    }}
+                    }
 
-                    a:hover {{
+                    a:hover {
                         text-decoration: underline;
-                    }}
-                "#,
-            )
+                    }
+                "#
+            .to_string()
         }
         _ => {
-            format!(
-                r#"
+            r#"
                 <link rel="stylesheet" href="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.11.1/styles/atom-one-dark.min.css">
-                "#,
-            )
+                "#
+            .to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        build::{self, BuildOptions, PageOutcome},
+        cli::{Content, find_target_files},
+        parser::Extensions,
+    };
+
+    /// A site in its own temporary directory, removed again when dropped.
+    struct TempSite {
+        dir: PathBuf,
+        dist: PathBuf,
+    }
+
+    impl TempSite {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("lssg-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let dist = dist_dir(&dir);
+            TempSite { dir, dist }
+        }
+
+        fn write(&self, path: &str, contents: &str) {
+            let path = self.dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        fn remove(&self, path: &str) {
+            fs::remove_file(self.dir.join(path)).unwrap();
+        }
+
+        fn exists(&self, path: &str) -> bool {
+            self.dir.join(path).exists()
+        }
+
+        /// Builds every page like `lssg build`, returning the pruned outputs.
+        fn build(&self, prune: bool) -> Vec<PathBuf> {
+            let config = Config {
+                title: "Test".to_string(),
+                description: String::new(),
+                theme: "frappe".to_string(),
+                content: Content {
+                    latex_enabled: false,
+                    extensions: Extensions::default(),
+                },
+            };
+
+            let mut outputs = BTreeSet::new();
+            for target in find_target_files(self.dir.join("content"), "md").unwrap() {
+                let outcome = build::build_page(
+                    &self.dir,
+                    &self.dist,
+                    &target,
+                    &config,
+                    BuildOptions::default(),
+                );
+                if let Ok(PageOutcome::Written(output, ..)) = outcome {
+                    outputs.insert(output);
+                }
+            }
+
+            record_build(&self.dist, outputs, prune, &[]).unwrap()
+        }
+    }
+
+    impl Drop for TempSite {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
         }
     }
+
+    fn paths(paths: &[&str]) -> BTreeSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn prunes_and_cleans_only_what_it_built() {
+        let site = TempSite::new("prune");
+        site.write("content/index.md", "# Home\n");
+        site.write("content/docs/intro.md", "# Intro\n");
+        assert!(site.build(false).is_empty());
+        assert_eq!(
+            read_manifest(&site.dist),
+            paths(&["docs/intro.html", "index.html"])
+        );
+
+        site.write("dist/notes.txt", "written by hand");
+        site.remove("content/docs/intro.md");
+
+        // Without `--prune` the stale page stays, but is still remembered
+        assert!(site.build(false).is_empty());
+        assert!(site.exists("dist/docs/intro.html"));
+        assert_eq!(
+            read_manifest(&site.dist),
+            paths(&["docs/intro.html", "index.html"])
+        );
+
+        assert_eq!(site.build(true), vec![PathBuf::from("docs/intro.html")]);
+        assert!(!site.exists("dist/docs"));
+        assert_eq!(read_manifest(&site.dist), paths(&["index.html"]));
+
+        assert_eq!(
+            clean(&site.dist).unwrap(),
+            vec![PathBuf::from("index.html")]
+        );
+        assert!(!site.exists("dist/index.html"));
+        assert!(!site.exists("dist/.lssg-manifest"));
+        assert!(site.exists("dist/notes.txt"));
+
+        site.remove("dist/notes.txt");
+        assert!(clean(&site.dist).unwrap().is_empty());
+        assert!(!site.exists("dist"));
+    }
+
+    #[test]
+    fn keeps_outputs_after_a_failed_build() {
+        let site = TempSite::new("failed");
+        site.write("content/index.md", "# Home\n");
+        site.write("content/old.md", "# Old\n");
+        site.build(false);
+        site.remove("content/old.md");
+
+        let failure = Error::Server("failed".to_string());
+        let removed = record_build(&site.dist, paths(&["index.html"]), true, &[failure]).unwrap();
+        assert!(removed.is_empty());
+        assert!(site.exists("dist/old.html"));
+        assert_eq!(
+            read_manifest(&site.dist),
+            paths(&["index.html", "old.html"])
+        );
+    }
+
+    #[test]
+    fn ignores_manifest_entries_outside_dist() {
+        let site = TempSite::new("manifest");
+        site.write("outside.html", "not ours");
+        site.write("dist/index.html", "ours");
+        site.write(
+            "dist/.lssg-manifest",
+            &format!(
+                "../outside.html\n{}\n\nindex.html\n",
+                site.dir.join("outside.html").display()
+            ),
+        );
+
+        assert_eq!(read_manifest(&site.dist), paths(&["index.html"]));
+        assert_eq!(
+            clean(&site.dist).unwrap(),
+            vec![PathBuf::from("index.html")]
+        );
+        assert!(site.exists("outside.html"));
+    }
+
+    #[test]
+    fn removes_directories_left_empty() {
+        let site = TempSite::new("remove");
+        site.write("dist/docs/guide/intro.html", "");
+        site.write("dist/docs/index.html", "");
+
+        assert!(remove_output(
+            &site.dist,
+            Path::new("docs/guide/intro.html")
+        ));
+        assert!(!site.exists("dist/docs/guide"));
+        assert!(site.exists("dist/docs/index.html"));
+        assert!(!remove_output(
+            &site.dist,
+            Path::new("docs/guide/intro.html")
+        ));
+
+        assert!(remove_output(&site.dist, Path::new("docs/index.html")));
+        assert!(!site.exists("dist/docs"));
+        assert!(site.exists("dist"));
+    }
+
+    #[test]
+    fn mirrors_sources_under_the_content_directory() {
+        let content = Path::new("site/content");
+        assert_eq!(
+            output_path(content, Path::new("site/content/docs/intro.md")),
+            PathBuf::from("docs/intro.html")
+        );
+        assert_eq!(
+            output_path(content, Path::new("site/content/index.md")),
+            PathBuf::from("index.html")
+        );
+    }
+
+    #[test]
+    fn finds_sources_written_to_the_same_output() {
+        let content = Path::new("content");
+        let targets = |paths: &[&str]| paths.iter().map(|p| content.join(p)).collect::<Vec<_>>();
+
+        assert!(
+            find_output_collisions(content, &targets(&["blog/intro.md", "docs/intro.md"]))
+                .is_empty()
+        );
+
+        let collisions =
+            find_output_collisions(content, &targets(&["docs/intro.md", "docs/Intro.md"]));
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].first, content.join("docs/intro.md"));
+        assert_eq!(collisions[0].second, content.join("docs/Intro.md"));
+        assert_eq!(collisions[0].output, PathBuf::from("docs/Intro.html"));
+    }
 }
@@ -0,0 +1,130 @@
+use std::{fmt, io, path::PathBuf};
+
+use crate::dist::OutputCollision;
+
+/// Every way an lssg command can fail, each with its own process exit code.
+#[derive(Debug)]
+pub enum Error {
+    /// `config.toml` is missing or invalid.
+    Config { path: PathBuf, message: String },
+    /// A filesystem operation on `path` failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A content file could not be parsed.
+    Parse { path: PathBuf, message: String },
+    /// A page could not be rendered or written to `dist/`.
+    Render { path: PathBuf, message: String },
+    /// The development server or file watcher failed.
+    Server(String),
+    /// Several content files would be written to the same output path, so
+    /// nothing was built.
+    Collisions(Vec<OutputCollision>),
+    /// `lssg check` found this many errors, or errors and warnings with
+    /// `--deny-warnings`.
+    Check(usize),
+    /// One or more pages failed to build.
+    Build(Vec<Error>),
+}
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Skips 2, which clap exits with on invalid command line arguments.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Check(_) => 1,
+            Error::Config { .. } => 3,
+            Error::Io { .. } => 4,
+            Error::Parse { .. } => 5,
+            Error::Render { .. } => 6,
+            Error::Server(_) => 7,
+            Error::Collisions(_) => 8,
+            // Use the code of the failures when they all agree
+            Error::Build(errors) => match errors.split_first() {
+                Some((first, rest)) if rest.iter().all(|e| e.exit_code() == first.exit_code()) => {
+                    first.exit_code()
+                }
+                _ => 9,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config { path, message } => write!(f, "{}: {}", path.display(), message),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+            Error::Render { path, message } => write!(f, "{}: {}", path.display(), message),
+            Error::Server(message) => write!(f, "{}", message),
+            Error::Check(count) => write!(f, "Found `{}` problems", count),
+            Error::Collisions(collisions) => {
+                write!(
+                    f,
+                    "`{}` pages would overwrite other pages in `dist/`",
+                    collisions.len()
+                )?;
+                for collision in collisions {
+                    write!(
+                        f,
+                        "\n  - {}: would be written to `dist/{}`, which `{}` is also written to",
+                        collision.second.display(),
+                        collision.output.display(),
+                        collision.first.display()
+                    )?;
+                }
+                Ok(())
+            }
+            Error::Build(errors) => {
+                write!(f, "`{}` pages failed to build", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_error() -> Error {
+        Error::Render {
+            path: PathBuf::from("a.md"),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn build_failures_exit_with_their_shared_code() {
+        assert_eq!(
+            Error::Build(vec![render_error(), render_error()]).exit_code(),
+            6
+        );
+
+        let parse_error = Error::Parse {
+            path: PathBuf::from("b.md"),
+            message: String::new(),
+        };
+        let mixed = Error::Build(vec![render_error(), parse_error]);
+        assert_eq!(mixed.exit_code(), 9);
+        assert_ne!(mixed.exit_code(), Error::Check(1).exit_code());
+        assert_ne!(mixed.exit_code(), 2);
+    }
+}
@@ -0,0 +1,330 @@
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value, value::Datetime};
+
+use crate::source::{LineIndex, Position};
+
+/// Per-page metadata read from a `+++` (TOML) or `---` (YAML-style) block at
+/// the start of a content file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub date: Option<Datetime>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub draft: bool,
+    pub template: Option<String>,
+    /// Any keys not covered by the fields above.
+    #[serde(flatten)]
+    pub extra: Table,
+}
+
+/// The keys [`FrontMatter`] has fields for.
+const KNOWN_KEYS: &[&str] = &["title", "description", "date", "tags", "draft", "template"];
+
+/// Keys whose YAML values are text however they look, so `title: 1984` is a
+/// title rather than a number.
+const TEXT_KEYS: &[&str] = &["title", "description", "tags", "template"];
+
+/// Where `body`, as returned by [`split_front_matter`], starts in `input`.
+pub fn body_start(input: &str, body: &str) -> Position {
+    let offset = input.len() - body.len();
+    LineIndex::new(input, Position::START).position(input, offset)
+}
+
+/// Splits the front matter off the start of `input`, returning the parsed
+/// metadata and the remaining markdown.
+///
+/// A leading `---` is only treated as front matter when it is closed by a
+/// second `---` line and everything inside looks like YAML front matter, so
+/// documents that open with a horizontal rule are left untouched.
+pub fn split_front_matter(input: &str) -> Result<(FrontMatter, &str), String> {
+    let mut lines = input.split_inclusive('\n');
+    let Some(first_line) = lines.next() else {
+        return Ok((FrontMatter::default(), input));
+    };
+
+    let delimiter = first_line.trim_end();
+    if delimiter != "+++" && delimiter != "---" {
+        return Ok((FrontMatter::default(), input));
+    }
+
+    let start = first_line.len();
+    let mut offset = start;
+    let mut block = None;
+    for line in lines {
+        if line.trim_end() == delimiter {
+            block = Some((&input[start..offset], offset + line.len()));
+            break;
+        }
+        offset += line.len();
+    }
+
+    let Some((source, end)) = block else {
+        if delimiter == "+++" {
+            return Err("Unclosed `+++` front matter block".to_string());
+        }
+        return Ok((FrontMatter::default(), input));
+    };
+
+    let front_matter = if delimiter == "+++" {
+        toml::from_str(source)
+            .map_err(|err| format!("Failed to parse TOML front matter: {}", err))?
+    } else {
+        if !looks_like_yaml(source) {
+            return Ok((FrontMatter::default(), input));
+        }
+
+        // Round-trip through TOML so both formats share one deserializer
+        let table = toml::to_string(&parse_yaml(source)?)
+            .map_err(|err| format!("Invalid front matter: {}", err))?;
+        toml::from_str(&table).map_err(|err| format!("Invalid front matter: {}", err))?
+    };
+
+    Ok((front_matter, &input[end..]))
+}
+
+/// Whether the inside of a `---` block is YAML front matter rather than text
+/// between two horizontal rules. A block opening with a front matter field is
+/// front matter, so YAML the parser doesn't support is reported rather than
+/// rendered. Otherwise every line has to be a `key: value` pair, a `- item` or
+/// a comment, and at least one key has to be a front matter field, so a page
+/// opening with `Note: read this first` between rules keeps it.
+fn looks_like_yaml(source: &str) -> bool {
+    let mut lines = source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let opens_with_field = lines
+        .clone()
+        .next()
+        .and_then(|line| line.split_once(':'))
+        .is_some_and(|(key, _)| KNOWN_KEYS.contains(&key));
+    if opens_with_field {
+        return true;
+    }
+
+    let mut known = false;
+    for line in lines.by_ref() {
+        if line == "-" || line.starts_with("- ") {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            return false;
+        };
+        if key.is_empty()
+            || key.contains(char::is_whitespace)
+            || !(value.is_empty() || value.starts_with(' '))
+        {
+            return false;
+        }
+        known |= KNOWN_KEYS.contains(&key);
+    }
+    known
+}
+
+/// Parses the subset of YAML used for front matter: `key: value` pairs with
+/// scalar values, inline `[a, b]` lists and block `- item` lists. Anything
+/// else, such as nested maps or `|` and `>` block scalars, is an error rather
+/// than being misread.
+fn parse_yaml(source: &str) -> Result<Table, String> {
+    let mut table = Table::new();
+    let mut lines = source.lines().enumerate().peekable();
+
+    while let Some((index, line)) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // Lines are numbered in the file, after the opening `---`
+        let unsupported = |what: &str| {
+            Err(format!(
+                "Failed to parse YAML front matter: {} on line {} aren't supported",
+                what,
+                index + 2
+            ))
+        };
+
+        if line.starts_with([' ', '\t']) {
+            return unsupported("nested values");
+        }
+
+        let Some((key, value)) = trimmed.split_once(':') else {
+            return Err(format!(
+                "Failed to parse YAML front matter: expected `key: value` on line {}",
+                index + 2
+            ));
+        };
+
+        let key = key.trim();
+        let scalar = match TEXT_KEYS.contains(&key) {
+            true => parse_yaml_string,
+            false => parse_yaml_scalar,
+        };
+
+        let value = value.trim();
+        if value.starts_with(['|', '>']) {
+            return unsupported("block scalars (`|` and `>`)");
+        }
+        if value.starts_with('{') || (value.starts_with('[') && !value.ends_with(']')) {
+            return unsupported("inline maps and lists spanning several lines");
+        }
+
+        let value = if value.is_empty() {
+            let mut items = Vec::new();
+            while let Some(item) = lines
+                .peek()
+                .and_then(|(_, l)| l.trim_start().strip_prefix('-'))
+            {
+                items.push(scalar(item.trim()));
+                lines.next();
+            }
+            // A key without a value or list items is null, which TOML can't hold
+            if items.is_empty() {
+                continue;
+            }
+            Value::Array(items)
+        } else if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Value::Array(
+                inner
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(scalar)
+                    .collect(),
+            )
+        } else {
+            scalar(value)
+        };
+
+        table.insert(key.to_string(), value);
+    }
+
+    Ok(table)
+}
+
+/// Strips the quotes around a YAML string, if it has any.
+fn unquote(value: &str) -> Option<&str> {
+    ['"', '\'']
+        .iter()
+        .find_map(|&q| value.strip_prefix(q).and_then(|v| v.strip_suffix(q)))
+}
+
+fn parse_yaml_string(value: &str) -> Value {
+    Value::String(unquote(value).unwrap_or(value).to_string())
+}
+
+fn parse_yaml_scalar(value: &str) -> Value {
+    if let Some(inner) = unquote(value) {
+        Value::String(inner.to_string())
+    } else if let Some(boolean) = parse_yaml_bool(value) {
+        Value::Boolean(boolean)
+    } else if let Ok(integer) = value.parse::<i64>() {
+        Value::Integer(integer)
+    } else if let Ok(float) = value.parse::<f64>() {
+        Value::Float(float)
+    } else if let Ok(datetime) = value.parse::<Datetime>() {
+        Value::Datetime(datetime)
+    } else {
+        Value::String(value.to_string())
+    }
+}
+
+/// Parses `true`/`false` along with the YAML 1.1 spellings `yes`/`no` and
+/// `on`/`off` that front matter often uses.
+fn parse_yaml_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &str) -> (FrontMatter, &str) {
+        split_front_matter(input).expect("front matter should parse")
+    }
+
+    #[test]
+    fn parses_yaml_scalars_and_lists() {
+        let (front_matter, body) = split(
+            "---\n# comment\ntitle: \"Hello: world\"\ndate: 2024-05-01\ndraft: yes\n\
+             tags: [a, 'b']\nweight: 3\nratio: 0.5\nlinks:\n  - one\n  - two\n---\nBody\n",
+        );
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello: world"));
+        assert_eq!(
+            front_matter.date.map(|d| d.to_string()).as_deref(),
+            Some("2024-05-01")
+        );
+        assert!(front_matter.draft);
+        assert_eq!(front_matter.tags, ["a", "b"]);
+        assert_eq!(front_matter.extra["weight"].as_integer(), Some(3));
+        assert_eq!(front_matter.extra["ratio"].as_float(), Some(0.5));
+        assert_eq!(
+            front_matter.extra["links"].as_array().map(Vec::len),
+            Some(2)
+        );
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn keeps_text_fields_as_strings() {
+        let (front_matter, _) =
+            split("---\ntitle: 1984\ndescription: true\ntemplate: 2\ntags: [2024, rust]\n---\n");
+
+        assert_eq!(front_matter.title.as_deref(), Some("1984"));
+        assert_eq!(front_matter.description.as_deref(), Some("true"));
+        assert_eq!(front_matter.template.as_deref(), Some("2"));
+        assert_eq!(front_matter.tags, ["2024", "rust"]);
+    }
+
+    #[test]
+    fn leaves_text_between_rules_alone() {
+        for input in [
+            "---\nNote: read this first\n---\nBody\n",
+            "---\nsee: the notes below\nand some prose\n---\n",
+            "---\n---\n",
+        ] {
+            let (front_matter, body) = split(input);
+            assert_eq!(body, input);
+            assert!(front_matter.title.is_none());
+        }
+    }
+
+    #[test]
+    fn reports_unsupported_yaml_instead_of_rendering_it() {
+        for (input, error) in [
+            (
+                "---\ntitle: Post\ndescription: >\n  folded text\n---\n",
+                "block scalars (`|` and `>`) on line 3",
+            ),
+            (
+                "---\ntitle: Post\nauthor:\n  name: Bob\n---\n",
+                "nested values on line 4",
+            ),
+            (
+                "---\ntitle: Post\nand some prose\n---\n",
+                "expected `key: value` on line 3",
+            ),
+            (
+                "---\ndate: 2024-01-01\ntags: [a,\n  b]\n---\n",
+                "spanning several lines on line 3",
+            ),
+        ] {
+            let message = split_front_matter(input).expect_err(input);
+            assert!(message.contains(error), "{}", message);
+        }
+    }
+
+    #[test]
+    fn rejects_unclosed_toml() {
+        assert!(split_front_matter("+++\ntitle = \"x\"\n").is_err());
+    }
+}
@@ -1,15 +1,29 @@
+mod build;
+mod check;
 mod cli;
+#[cfg(test)]
+mod conformance;
 mod dist;
+mod entities;
+mod error;
+mod front_matter;
 mod parser;
+mod scaffold;
+mod serve;
+mod source;
 mod translate;
+mod watch;
 
-use std::{fs::read_to_string, process, time::Instant};
+use std::{collections::BTreeSet, path::Path, process, time::Instant};
 
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
 use owo_colors::OwoColorize;
 
-use crate::cli::{Cli, find_target_files, get_current_directory};
+use crate::{
+    cli::{Cli, find_target_files, get_current_directory},
+    error::Error,
+};
 
 fn print_json_value(key: &str, value: &serde_json::Value, indent: usize) {
     let indent_str = "  ".repeat(indent);
@@ -31,109 +45,194 @@ fn print_json_value(key: &str, value: &serde_json::Value, indent: usize) {
     }
 }
 
-fn main() {
-    let total_start = Instant::now();
-    let cli = Cli::parse();
-
-    let config = match cli::get_config() {
-        Ok(config) => config,
-        Err(err) => {
-            eprintln!("{}: {}", "Error".bold().red(), err);
-            std::process::exit(1);
-        }
-    };
+/// Loads `config.toml`, printing it when `verbose` is set.
+fn load_config(current_dir: &Path, verbose: bool) -> Result<cli::Config, Error> {
+    let config = cli::get_config(current_dir)?;
 
-    if cli.verbose {
+    if verbose {
         println!("{}", "Config".green().bold());
-        let value = serde_json::to_value(&config).unwrap();
-        if let serde_json::Value::Object(map) = value {
+        if let Ok(serde_json::Value::Object(map)) = serde_json::to_value(&config) {
             for (key, val) in map {
                 print_json_value(&key, &val, 1);
             }
         }
     }
 
-    let current_dir = get_current_directory();
+    Ok(config)
+}
+
+fn main() {
+    let total_start = Instant::now();
+    let cli = Cli::parse();
+    let verbose = cli.verbose;
+
+    if let Err(err) = run(cli) {
+        eprintln!("{}: {}", "Error".bold().red(), err);
+        process::exit(err.exit_code());
+    }
+
+    let total_duration = total_start.elapsed();
+
+    if verbose {
+        println!(
+            "{}: `{}`ms",
+            "Duration".green().bold(),
+            total_duration.as_millis()
+        );
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    let current_dir = get_current_directory()?;
 
     match cli.cmd {
-        cli::Command::Build => {
+        cli::Command::Build { prune } => {
+            let config = load_config(&current_dir, cli.verbose)?;
+
             println!("{} `build`", "Running".green().bold());
 
             let content_dir = current_dir.join("content");
 
-            let targets = find_target_files(content_dir, "md");
+            let targets = find_target_files(content_dir.clone(), "md")?;
 
             println!(" {} `{}` targets", "Found".green().bold(), targets.len());
 
             let bar = ProgressBar::new(targets.len() as u64);
-            bar.set_style(
-                ProgressStyle::with_template(" [{bar:57}] {pos}/{len}")
-                    .unwrap()
-                    .progress_chars("=> "),
-            );
+            if let Ok(style) = ProgressStyle::with_template(" [{bar:57}] {pos}/{len}") {
+                bar.set_style(style.progress_chars("=> "));
+            }
 
             if targets.is_empty() {
                 println!("{} No targets found", "Warning".yellow().bold());
-                process::exit(0);
+                if !prune {
+                    return Ok(());
+                }
+            }
+
+            let collisions = dist::find_output_collisions(&content_dir, &targets);
+            if !collisions.is_empty() {
+                return Err(Error::Collisions(collisions));
             }
 
+            let options = build::BuildOptions {
+                verbose: cli.verbose,
+                live_reload: false,
+            };
+            let dist_dir = dist::dist_dir(&current_dir);
+
+            let mut outputs = BTreeSet::new();
+            let mut failures = Vec::new();
+            let mut tasks = Vec::new();
+
+            // A hidden bar, such as when output is piped, drops its messages
+            let print = |message: String| match bar.is_hidden() {
+                true => println!("{}", message),
+                false => bar.println(message),
+            };
+
             for target in targets {
-                let content = read_to_string(&target)
-                    .map_err(|e| {
-                        format!(
-                            "{} Failed to read file contents: {}",
-                            "Error".bold().red(),
-                            e
-                        )
-                    })
-                    .unwrap();
-
-                let parser = parser::MarkdownParser::new(content);
-                let nodes = parser.parse();
-
-                if cli.verbose {
-                    for node in &nodes {
-                        println!("{:?}", node);
+                match build::build_page(&current_dir, &dist_dir, &target, &config, options) {
+                    Ok(build::PageOutcome::Written(output, count, warnings)) => {
+                        for warning in &warnings {
+                            print(build::format_warning(&current_dir, &target, warning));
+                        }
+                        if count.total > 0 {
+                            tasks.push((output.clone(), count));
+                        }
+                        outputs.insert(output);
                     }
+                    Ok(build::PageOutcome::Draft) => {
+                        if cli.verbose {
+                            print(format!(
+                                " {} draft `{}`",
+                                "Skipping".yellow().bold(),
+                                target.display()
+                            ));
+                        }
+                    }
+                    Err(err) => failures.push(err),
                 }
 
-                let translator = translate::Translator::new(&nodes);
-                let translated = translator.translate("");
+                bar.inc(1);
+            }
+            bar.finish();
 
-                let file_name = &target.file_stem();
+            for (output, count) in &tasks {
+                println!(
+                    " {} `{}/{}` done in `dist/{}`",
+                    "Tasks".green().bold(),
+                    count.done,
+                    count.total,
+                    output.display()
+                );
+            }
 
-                if file_name.is_none() {
-                    println!(
-                        "{} Failed to find file name: {}",
-                        "Error".bold().red(),
-                        target.display()
-                    );
-                }
+            for removed in dist::record_build(&dist_dir, outputs, prune, &failures)? {
+                println!(" {} `dist/{}`", "Pruned".yellow().bold(), removed.display());
+            }
 
-                let file_name = file_name.unwrap();
-                let output_file = format!("{}.html", file_name.to_str().unwrap());
+            if !failures.is_empty() {
+                return Err(Error::Build(failures));
+            }
+        }
+        cli::Command::Check { deny_warnings } => {
+            let config = load_config(&current_dir, cli.verbose)?;
 
-                dist::create_dist(&current_dir);
+            println!("{} `check`", "Running".green().bold());
 
-                dist::create_file(
-                    &current_dir,
-                    &output_file,
-                    dist::create_dom(translated.as_str(), &config).as_str(),
-                );
+            let report = check::check_site(&current_dir, &config)?;
+            for diagnostic in &report.diagnostics {
+                check::print_diagnostic(diagnostic);
+            }
 
-                bar.inc(1);
+            println!(
+                " {} `{}` files, `{}` errors, `{}` warnings",
+                "Checked".green().bold(),
+                report.files,
+                report.errors(),
+                report.warnings()
+            );
+
+            let problems = match deny_warnings {
+                true => report.errors() + report.warnings(),
+                false => report.errors(),
+            };
+            if problems > 0 {
+                return Err(Error::Check(problems));
             }
-            bar.finish();
         }
-    }
+        cli::Command::Clean => {
+            let removed = dist::clean(&dist::dist_dir(&current_dir))?;
+            println!(
+                " {} `{}` generated files",
+                "Removed".green().bold(),
+                removed.len()
+            );
+        }
+        cli::Command::Serve { port } => {
+            let config = load_config(&current_dir, cli.verbose)?;
 
-    let total_duration = total_start.elapsed();
+            println!("{} `serve`", "Running".green().bold());
 
-    if cli.verbose {
-        println!(
-            "{}: `{}`ms",
-            "Duration".green().bold(),
-            total_duration.as_millis()
-        );
+            serve::serve(&current_dir, config, port, cli.verbose)?;
+        }
+        cli::Command::Watch { poll } => {
+            let config = load_config(&current_dir, cli.verbose)?;
+
+            println!("{} `watch`", "Running".green().bold());
+
+            watch::watch(&current_dir, config, poll, cli.verbose)?;
+        }
+        cli::Command::Init { dir } => {
+            for file in scaffold::init(&dir)? {
+                println!(" {} `{}`", "Created".green().bold(), file.display());
+            }
+        }
+        cli::Command::New { path } => {
+            let file = scaffold::new_page(&current_dir.join("content"), &path)?;
+            println!(" {} `{}`", "Created".green().bold(), file.display());
+        }
     }
+
+    Ok(())
 }
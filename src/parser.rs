@@ -1,15 +1,25 @@
+use std::collections::HashMap;
+
 use logos::Logos;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities,
+    source::{LineIndex, Position, SourceSpan},
+};
 
 #[derive(Debug)]
 pub struct MarkdownCodeBlock {
     pub language: String,
     pub content: String,
+    pub span: SourceSpan,
 }
 
 #[derive(Debug)]
 pub struct MarkdownHeading {
     pub level: u8,
-    pub content: String,
+    pub content: Vec<MarkdownSpan>,
+    pub span: SourceSpan,
 }
 
 #[derive(Debug)]
@@ -18,18 +28,133 @@ pub enum MarkdownVariant {
     Italic,
     Normal,
     InlineCode,
+    /// An inline `[text](url)` or reference link, the span children are the
+    /// link text.
+    Link {
+        url: String,
+        title: Option<String>,
+    },
+    /// An inline `![alt](url)` or reference image, the span children are the
+    /// alt text.
+    Image {
+        url: String,
+        title: Option<String>,
+    },
+    /// An `<https://...>` or `<user@example.com>` autolink, the span content
+    /// is the destination.
+    AutoLink,
+    /// A hard line break, from a line ending in two spaces or a backslash.
+    LineBreak,
+    Strikethrough,
+    Highlight,
+    Superscript,
+    Subscript,
+    /// A `[^label]` footnote reference, holding the label.
+    FootnoteReference(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MarkdownListType {
     Numbered,
     Bulleted,
 }
 
+#[derive(Debug)]
+pub struct MarkdownListItem {
+    pub children: Vec<MarkdownNodes>,
+    /// Whether a `- [ ]` or `- [x]` task list item is checked, `None` for
+    /// other items.
+    pub checked: Option<bool>,
+    pub span: SourceSpan,
+}
+
 #[derive(Debug)]
 pub struct MarkdownList {
     pub ty: MarkdownListType,
-    pub items: Vec<String>,
+    /// The number of the first item of a numbered list, `None` for bulleted
+    /// lists.
+    pub start: Option<u32>,
+    /// Whether a blank line separates any of the items or the children of an
+    /// item, which renders item paragraphs as `<p>` rather than inline.
+    pub loose: bool,
+    pub items: Vec<MarkdownListItem>,
+    pub span: SourceSpan,
+}
+
+/// A list item marker found at the start of a line.
+struct ListMarker<'a> {
+    ty: MarkdownListType,
+    /// The item number of a numbered list marker.
+    number: Option<u32>,
+    /// Column at which the item's content starts; continuation lines must be
+    /// indented at least this far to belong to the item.
+    content_offset: usize,
+    content: Line<'a>,
+}
+
+/// Tracks whether the lines collected for a block quote or list item end in
+/// a paragraph, which a following line can continue lazily without the quote
+/// marker or indentation.
+#[derive(Default)]
+struct LazyContinuation {
+    in_fence: bool,
+    in_paragraph: bool,
+}
+
+impl LazyContinuation {
+    fn push(&mut self, line: Line) {
+        let (content, _) = MarkdownParser::strip_container_markers(line);
+        if self.in_fence {
+            self.in_fence = !MarkdownParser::is_closing_fence(content);
+            self.in_paragraph = false;
+        } else if MarkdownParser::starts_with(content, &[Token::Backtick; 3]) {
+            self.in_fence = true;
+            self.in_paragraph = false;
+        } else {
+            self.in_paragraph =
+                !MarkdownParser::trim(content).is_empty() && !MarkdownParser::starts_block(content);
+        }
+    }
+
+    /// Whether `line` continues the paragraph the collected lines end with.
+    fn accepts(&self, line: Line) -> bool {
+        self.in_paragraph
+            && !MarkdownParser::trim(line).is_empty()
+            && !MarkdownParser::starts_block(line)
+            // Even an empty list item ends the paragraph here
+            && MarkdownParser::parse_list_marker(line).is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarkdownAlignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug)]
+pub struct MarkdownTable {
+    pub alignments: Vec<MarkdownAlignment>,
+    pub header: Vec<Vec<MarkdownSpan>>,
+    pub rows: Vec<Vec<Vec<MarkdownSpan>>>,
+    pub span: SourceSpan,
+}
+
+#[derive(Debug)]
+pub struct MarkdownBlockQuote {
+    pub children: Vec<MarkdownNodes>,
+    pub span: SourceSpan,
+}
+
+/// A `[^label]: text` footnote definition, rendered with the other footnotes
+/// at the end of the page.
+#[derive(Debug)]
+pub struct MarkdownFootnote {
+    pub label: String,
+    pub children: Vec<MarkdownNodes>,
+    pub span: SourceSpan,
 }
 
 #[derive(Debug)]
@@ -39,11 +164,20 @@ pub enum MarkdownNodes {
     List(MarkdownList),
     Link(MarkdownLink),
     CodeBlock(MarkdownCodeBlock),
-    HorizontalRule,
+    BlockQuote(MarkdownBlockQuote),
+    Table(MarkdownTable),
+    HorizontalRule(SourceSpan),
+    Footnote(MarkdownFootnote),
 }
 
-#[derive(Logos)]
-#[logos(extras = (usize, usize))]
+/// How far lines after the first of a footnote definition are indented to
+/// continue it with more blocks.
+const FOOTNOTE_INDENT: usize = 4;
+
+/// The tokens markdown source is split into. Punctuation that means something
+/// to the parser gets its own token, while other text is grouped into numbers
+/// and words, so every character of the input belongs to exactly one token.
+#[derive(Logos, Debug, Clone, Copy, PartialEq)]
 pub enum Token {
     #[token("#")]
     Hash,
@@ -51,236 +185,2144 @@ pub enum Token {
     Asterisk,
     #[token("-")]
     Dash,
-    #[token(" ")]
-    Space,
-    #[token("!")]
-    ExclamationMark,
+    #[token("+")]
+    Plus,
+    #[token("=")]
+    Equals,
+    #[token("_")]
+    Underscore,
     #[token("`")]
     Backtick,
-    #[regex(r"[a-zA-Z0-9]+")]
+    #[token("~")]
+    Tilde,
+    #[token("!")]
+    ExclamationMark,
+    #[token("[")]
+    LeftBracket,
+    #[token("]")]
+    RightBracket,
+    #[token("(")]
+    LeftParen,
+    #[token(")")]
+    RightParen,
+    #[token("<")]
+    LessThan,
+    #[token(">")]
+    GreaterThan,
+    #[token("|")]
+    Pipe,
+    #[token("\\")]
+    Backslash,
+    #[token(":")]
+    Colon,
+    #[token(".")]
+    Period,
+    /// Any other ASCII punctuation character.
+    #[regex(r#"["$%&',/;?@^{}]"#)]
+    Punctuation,
+    #[token(" ")]
+    Space,
+    #[token("\t")]
+    Tab,
+    #[regex(r"\r\n|\n|\r")]
+    Newline,
+    #[regex("[0-9]+")]
+    Number,
+    /// A run of any other characters, including all non-ASCII text.
+    #[regex(r#"[^ \t\r\n!-/:-@\[-`{-~0-9]+"#)]
     Word,
 }
 
+/// A token along with the text it covers and where that starts in the input.
+#[derive(Debug, Clone, Copy)]
+struct Lexeme<'a> {
+    token: Token,
+    text: &'a str,
+    offset: usize,
+}
+
+impl Lexeme<'_> {
+    fn end(&self) -> usize {
+        self.offset + self.text.len()
+    }
+
+    fn is_blank(&self) -> bool {
+        matches!(self.token, Token::Space | Token::Tab)
+    }
+
+    fn is_whitespace(&self) -> bool {
+        self.is_blank() || self.token == Token::Newline
+    }
+}
+
+/// The tokens of a single line, or of any other run of tokens.
+type Line<'a> = &'a [Lexeme<'a>];
+
 #[derive(Debug)]
 pub struct MarkdownSpan {
     pub content: String,
     pub variant: MarkdownVariant,
+    /// The spans nested inside emphasis, links and images, which have no
+    /// content of their own.
+    pub children: Vec<MarkdownSpan>,
+    pub span: SourceSpan,
 }
 
 #[derive(Debug)]
 pub struct MarkdownParagraph {
     pub spans: Vec<MarkdownSpan>,
+    pub span: SourceSpan,
 }
 
 #[derive(Debug)]
 pub struct MarkdownLink {
-    pub alt: String,
+    pub alt: Vec<MarkdownSpan>,
     pub url: String,
+    pub title: Option<String>,
     pub is_image: bool,
+    pub span: SourceSpan,
+}
+
+/// The target of a `[label]: url "title"` link reference definition.
+struct LinkDefinition {
+    url: String,
+    title: Option<String>,
+}
+
+/// The pieces of a `[text](url)` or `![alt](url)` construct, or of a
+/// reference link resolved against its definition.
+struct LinkSyntax<'a> {
+    is_image: bool,
+    text: Line<'a>,
+    url: String,
+    title: Option<String>,
+    /// Number of tokens in the whole construct.
+    len: usize,
+}
+
+/// A run of `*`, `_`, `~`, `=` or `^` characters that may open or close
+/// emphasis or one of the other spans marked by a delimiter on both sides.
+#[derive(Debug, Clone, Copy)]
+struct Delimiter {
+    character: char,
+    /// Characters of the run not yet used by a span.
+    count: usize,
+    /// Length of the whole run.
+    length: usize,
+    can_open: bool,
+    can_close: bool,
+    /// Where the unused characters start and end in the input.
+    start: usize,
+    end: usize,
+}
+
+impl Delimiter {
+    fn text(&self) -> String {
+        self.character.to_string().repeat(self.count)
+    }
+
+    fn is_emphasis(&self) -> bool {
+        matches!(self.character, '*' | '_')
+    }
+}
+
+/// Inline syntax beyond CommonMark, each enabled unless turned off in the
+/// `[content]` section of the config.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Extensions {
+    /// `~~text~~` renders as `<del>`.
+    pub strikethrough: bool,
+    /// `==text==` renders as `<mark>`.
+    pub highlight: bool,
+    /// `^text^` renders as `<sup>`.
+    pub superscript: bool,
+    /// `~text~` renders as `<sub>`.
+    pub subscript: bool,
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Extensions {
+            strikethrough: true,
+            highlight: true,
+            superscript: true,
+            subscript: true,
+        }
+    }
+}
+
+/// An inline element, or a delimiter run that hasn't been matched yet.
+enum Inline {
+    Span(MarkdownSpan),
+    Delimiter(Delimiter),
 }
 
 pub struct MarkdownParser {
     input: String,
+    lines: LineIndex,
+    extensions: Extensions,
+    /// Link reference definitions by normalized label.
+    definitions: HashMap<String, LinkDefinition>,
 }
 
 impl MarkdownParser {
     pub fn new(input: String) -> Self {
-        MarkdownParser { input }
+        let lines = LineIndex::new(&input, Position::START);
+        // Most documents have no definitions, so skip lexing them twice
+        let definitions = match input.contains("]:") {
+            true => Self::collect_link_definitions(&Self::lex(&input)),
+            false => HashMap::new(),
+        };
+        MarkdownParser {
+            input,
+            lines,
+            extensions: Extensions::default(),
+            definitions,
+        }
     }
 
-    fn parse_inline_formatting(&self, text: &str) -> Vec<MarkdownSpan> {
-        let mut spans = Vec::new();
-        let mut chars = text.chars().peekable();
-        let mut buffer = String::new();
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
 
-        enum State {
-            Normal,
-            Italic,
-            Bold,
-            InlineCode,
-        }
-
-        let mut state = State::Normal;
-
-        while let Some(c) = chars.next() {
-            match c {
-                '*' => {
-                    if chars.peek() == Some(&'*') {
-                        chars.next();
-                        if !buffer.is_empty() {
-                            spans.push(MarkdownSpan {
-                                content: buffer.clone(),
-                                variant: match state {
-                                    State::Normal => MarkdownVariant::Normal,
-                                    State::Italic => MarkdownVariant::Italic,
-                                    State::Bold => MarkdownVariant::Bold,
-                                    State::InlineCode => MarkdownVariant::InlineCode,
-                                },
-                            });
-                            buffer.clear();
-                        }
-                        state = match state {
-                            State::Bold => State::Normal,
-                            _ => State::Bold,
-                        };
-                    } else {
-                        if !buffer.is_empty() {
-                            spans.push(MarkdownSpan {
-                                content: buffer.clone(),
-                                variant: match state {
-                                    State::Normal => MarkdownVariant::Normal,
-                                    State::Italic => MarkdownVariant::Italic,
-                                    State::Bold => MarkdownVariant::Bold,
-                                    State::InlineCode => MarkdownVariant::InlineCode,
-                                },
-                            });
-                            buffer.clear();
-                        }
-                        state = match state {
-                            State::Italic => State::Normal,
-                            _ => State::Italic,
-                        };
+    /// Places the input at `start` in its file, such as the body of a page
+    /// after its front matter, so spans point into the file.
+    pub fn starting_at(mut self, start: Position) -> Self {
+        self.lines = LineIndex::new(&self.input, start);
+        self
+    }
+
+    fn span(&self, start: usize, end: usize) -> SourceSpan {
+        self.lines.span(&self.input, start, end)
+    }
+
+    /// The source span covered by `tokens`.
+    fn span_of(&self, tokens: &[Lexeme]) -> SourceSpan {
+        let start = tokens.first().map_or(0, |first| first.offset);
+        let end = tokens.last().map_or(start, Lexeme::end);
+        self.span(start, end)
+    }
+
+    /// Splits `input` into lexemes. The tokens cover every character, so
+    /// anything the lexer fails to match is kept as a word.
+    fn lex(input: &str) -> Vec<Lexeme<'_>> {
+        let mut lexer = Token::lexer(input);
+        let mut lexemes = Vec::new();
+
+        while let Some(token) = lexer.next() {
+            lexemes.push(Lexeme {
+                token: token.unwrap_or(Token::Word),
+                text: lexer.slice(),
+                offset: lexer.span().start,
+            });
+        }
+
+        lexemes
+    }
+
+    /// Splits tokens into lines, leaving out the newline tokens between them.
+    fn split_lines<'a>(tokens: Line<'a>) -> Vec<Line<'a>> {
+        let mut lines: Vec<_> = tokens
+            .split(|lexeme| lexeme.token == Token::Newline)
+            .collect();
+
+        // Like `str::lines`, a final newline doesn't start another line
+        if tokens
+            .last()
+            .is_some_and(|last| last.token == Token::Newline)
+        {
+            lines.pop();
+        }
+
+        lines
+    }
+
+    fn text_of(tokens: &[Lexeme]) -> String {
+        tokens.iter().map(|lexeme| lexeme.text).collect()
+    }
+
+    fn starts_with(tokens: &[Lexeme], expected: &[Token]) -> bool {
+        tokens.len() >= expected.len()
+            && tokens
+                .iter()
+                .zip(expected)
+                .all(|(lexeme, &token)| lexeme.token == token)
+    }
+
+    /// Whether there is at least one token and every token is `token`.
+    fn is_only(tokens: &[Lexeme], token: Token) -> bool {
+        !tokens.is_empty() && tokens.iter().all(|lexeme| lexeme.token == token)
+    }
+
+    fn contains(tokens: &[Lexeme], token: Token) -> bool {
+        tokens.iter().any(|lexeme| lexeme.token == token)
+    }
+
+    fn trim_start<'t, 'a>(tokens: &'t [Lexeme<'a>]) -> &'t [Lexeme<'a>] {
+        let whitespace = tokens.iter().take_while(|l| l.is_whitespace()).count();
+        &tokens[whitespace..]
+    }
+
+    fn trim_end<'t, 'a>(tokens: &'t [Lexeme<'a>]) -> &'t [Lexeme<'a>] {
+        let whitespace = tokens
+            .iter()
+            .rev()
+            .take_while(|l| l.is_whitespace())
+            .count();
+        &tokens[..tokens.len() - whitespace]
+    }
+
+    fn trim<'t, 'a>(tokens: &'t [Lexeme<'a>]) -> &'t [Lexeme<'a>] {
+        Self::trim_end(Self::trim_start(tokens))
+    }
+
+    /// Where the last non-blank token of a line ends, if it has any.
+    fn end_of(line: Line) -> Option<usize> {
+        Self::trim_end(line).last().map(Lexeme::end)
+    }
+
+    /// Whether a token is a single ASCII punctuation character, which a
+    /// backslash before it escapes.
+    fn is_escapable(lexeme: &Lexeme) -> bool {
+        lexeme.text.len() == 1 && lexeme.text.as_bytes()[0].is_ascii_punctuation()
+    }
+
+    /// Parses a `&name;`, `&#123;` or `&#x7b;` character reference at the
+    /// start of `tokens`, returning its text and the number of tokens it covers.
+    fn parse_entity(tokens: &[Lexeme]) -> Option<(String, usize)> {
+        let end = tokens
+            .iter()
+            .take(12)
+            .position(|lexeme| lexeme.text == ";")?;
+        let reference = Self::text_of(&tokens[1..end]);
+
+        let text = match reference.strip_prefix('#') {
+            Some(number) => {
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex)
+                        if (1..=6).contains(&hex.len())
+                            && hex.bytes().all(|b| b.is_ascii_hexdigit()) =>
+                    {
+                        u32::from_str_radix(hex, 16).ok()?
                     }
-                }
-                '`' => {
-                    if !buffer.is_empty() {
-                        spans.push(MarkdownSpan {
-                            content: buffer.clone(),
-                            variant: match state {
-                                State::Normal => MarkdownVariant::Normal,
-                                State::Italic => MarkdownVariant::Italic,
-                                State::Bold => MarkdownVariant::Bold,
-                                State::InlineCode => MarkdownVariant::InlineCode,
-                            },
-                        });
-                        buffer.clear();
+                    None if (1..=7).contains(&number.len())
+                        && number.bytes().all(|b| b.is_ascii_digit()) =>
+                    {
+                        number.parse().ok()?
                     }
-                    state = match state {
-                        State::InlineCode => State::Normal,
-                        _ => State::InlineCode,
-                    };
+                    _ => return None,
+                };
+                // Like HTML, invalid code points and NUL become a replacement character
+                char::from_u32(code)
+                    .filter(|&c| c != '\0')
+                    .unwrap_or(char::REPLACEMENT_CHARACTER)
+                    .to_string()
+            }
+            None => entities::lookup(&reference)?.to_string(),
+        };
+
+        Some((text, end + 1))
+    }
+
+    /// The text of `tokens` with backslash escapes and character references
+    /// resolved, for link destinations, titles and code fence info strings.
+    fn unescape(tokens: &[Lexeme]) -> String {
+        let mut text = String::new();
+        let mut i = 0;
+
+        while let Some(lexeme) = tokens.get(i) {
+            i += match lexeme.token {
+                Token::Backslash if tokens.get(i + 1).is_some_and(Self::is_escapable) => {
+                    text.push_str(tokens[i + 1].text);
+                    2
                 }
-                _ => buffer.push(c),
+                _ => match Some(&tokens[i..])
+                    .filter(|_| lexeme.text == "&")
+                    .and_then(Self::parse_entity)
+                {
+                    Some((entity, len)) => {
+                        text.push_str(&entity);
+                        len
+                    }
+                    None => {
+                        text.push_str(lexeme.text);
+                        1
+                    }
+                },
+            };
+        }
+
+        text
+    }
+
+    fn backtick_run(tokens: &[Lexeme]) -> usize {
+        tokens
+            .iter()
+            .take_while(|lexeme| lexeme.token == Token::Backtick)
+            .count()
+    }
+
+    /// Parses a code span at the start of `tokens`, which opens with a run of
+    /// backticks and closes at the next run of the same length. Returns the
+    /// code and the number of tokens the span covers.
+    fn parse_code_span(tokens: &[Lexeme]) -> Option<(String, usize)> {
+        let opening = Self::backtick_run(tokens);
+        let mut i = opening;
+
+        while i < tokens.len() {
+            let closing = Self::backtick_run(&tokens[i..]);
+            if closing == 0 {
+                i += 1;
+                continue;
             }
+            if closing != opening {
+                i += closing;
+                continue;
+            }
+
+            let code: String = tokens[opening..i]
+                .iter()
+                .map(|lexeme| match lexeme.token {
+                    Token::Newline => " ",
+                    _ => lexeme.text,
+                })
+                .collect();
+            // A space on both sides is stripped, so code can start or end
+            // with a backtick
+            let code = match code.starts_with(' ')
+                && code.ends_with(' ')
+                && !code.bytes().all(|b| b == b' ')
+            {
+                true => code[1..code.len() - 1].to_string(),
+                false => code,
+            };
+
+            return Some((code, i + closing));
         }
 
-        if !buffer.is_empty() {
-            spans.push(MarkdownSpan {
-                content: buffer,
-                variant: match state {
-                    State::Normal => MarkdownVariant::Normal,
-                    State::Italic => MarkdownVariant::Italic,
-                    State::Bold => MarkdownVariant::Bold,
-                    State::InlineCode => MarkdownVariant::InlineCode,
-                },
-            });
+        None
+    }
+
+    /// How many tokens at the start of `tokens` are taken as they are by
+    /// constructs that contain them, which is an escaped character or a whole
+    /// code span or run of backticks.
+    fn opaque_len(tokens: &[Lexeme]) -> usize {
+        match tokens.first().map(|lexeme| lexeme.token) {
+            Some(Token::Backslash) if tokens.get(1).is_some_and(Self::is_escapable) => 2,
+            Some(Token::Backtick) => Self::parse_code_span(tokens)
+                .map_or_else(|| Self::backtick_run(tokens), |(_, len)| len),
+            _ => 1,
         }
+    }
 
-        spans
+    /// Finds the `]` matching the `[` before `tokens[start]`, allowing balanced
+    /// brackets inside the text and skipping over escaped brackets and code
+    /// spans.
+    fn link_text_end(tokens: &[Lexeme], start: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut end = start;
+        loop {
+            match tokens.get(end)?.token {
+                Token::LeftBracket => depth += 1,
+                Token::RightBracket if depth == 0 => return Some(end),
+                Token::RightBracket => depth -= 1,
+                _ => {}
+            }
+            end += Self::opaque_len(&tokens[end..]);
+        }
     }
 
-    pub fn parse(&self) -> Vec<MarkdownNodes> {
-        let mut nodes = Vec::new();
-        let mut lines = self.input.lines().peekable();
+    /// Finds the `]` closing a link label that starts at `tokens[start]`,
+    /// which can't contain unescaped brackets.
+    fn label_end(tokens: &[Lexeme], start: usize) -> Option<usize> {
+        let mut end = start;
+        loop {
+            match tokens.get(end)?.token {
+                Token::RightBracket => return Some(end),
+                Token::LeftBracket => return None,
+                Token::Backslash if tokens.get(end + 1).is_some_and(Self::is_escapable) => end += 1,
+                _ => {}
+            }
+            end += 1;
+        }
+    }
 
-        while let Some(line) = lines.next() {
-            let trimmed = line.trim_start();
+    /// Normalizes a link label for matching, which ignores case and how
+    /// whitespace is written.
+    fn normalize_label(label: &str) -> String {
+        label
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
 
-            if trimmed.starts_with("---") {
-                nodes.push(MarkdownNodes::HorizontalRule)
-            } else if trimmed.starts_with("###") {
-                nodes.push(MarkdownNodes::Heading(MarkdownHeading {
-                    level: 3,
-                    content: trimmed[3..].trim().to_string(),
-                }));
-            } else if trimmed.starts_with("##") {
-                nodes.push(MarkdownNodes::Heading(MarkdownHeading {
-                    level: 2,
-                    content: trimmed[2..].trim().to_string(),
-                }));
-            } else if trimmed.starts_with("#") {
-                nodes.push(MarkdownNodes::Heading(MarkdownHeading {
-                    level: 1,
-                    content: trimmed[1..].trim().to_string(),
-                }));
-            } else if trimmed.starts_with("```") {
-                let lang = trimmed[3..].trim().to_string();
-                let mut content = String::new();
-                while let Some(next) = lines.next() {
-                    if next.trim() == "```" {
-                        break;
-                    } else {
-                        content.push_str(next);
-                        content.push('\n');
-                    }
-                }
-                nodes.push(MarkdownNodes::CodeBlock(MarkdownCodeBlock {
-                    language: lang,
-                    content,
-                }));
-            } else if trimmed.starts_with("-") {
-                let mut items = vec![trimmed[1..].trim().to_string()];
-                while let Some(peeked) = lines.peek() {
-                    if peeked.trim_start().starts_with("-") {
-                        let item_line = lines.next().unwrap();
-                        items.push(item_line.trim_start()[1..].trim().to_string());
-                    } else {
-                        break;
+    fn skip_whitespace(tokens: &[Lexeme], from: usize) -> usize {
+        from + tokens.get(from..).map_or(0, |rest| {
+            rest.iter()
+                .take_while(|lexeme| lexeme.is_whitespace())
+                .count()
+        })
+    }
+
+    /// Parses a link destination at the start of `tokens`, either `<...>` or
+    /// text without whitespace and with balanced parentheses. Returns the URL
+    /// and the number of tokens it covers.
+    fn parse_link_destination(tokens: &[Lexeme]) -> Option<(String, usize)> {
+        if tokens.first()?.token == Token::LessThan {
+            let mut end = 1;
+            loop {
+                match tokens.get(end)?.token {
+                    Token::GreaterThan => break,
+                    Token::LessThan | Token::Newline => return None,
+                    Token::Backslash if tokens.get(end + 1).is_some_and(Self::is_escapable) => {
+                        end += 1
                     }
+                    _ => {}
                 }
-                nodes.push(MarkdownNodes::List(MarkdownList {
-                    ty: MarkdownListType::Bulleted,
-                    items,
-                }));
-            } else if trimmed
-                .chars()
-                .next()
-                .map(|c| c.is_digit(10))
-                .unwrap_or(false)
-                && trimmed.contains('.')
+                end += 1;
+            }
+            return Some((Self::unescape(&tokens[1..end]), end + 1));
+        }
+
+        let mut depth = 0;
+        let mut end = 0;
+        while let Some(lexeme) = tokens.get(end) {
+            match lexeme.token {
+                _ if lexeme.is_whitespace() => break,
+                Token::LeftParen => depth += 1,
+                Token::RightParen if depth == 0 => break,
+                Token::RightParen => depth -= 1,
+                Token::Backslash if tokens.get(end + 1).is_some_and(Self::is_escapable) => end += 1,
+                _ => {}
+            }
+            end += 1;
+        }
+
+        (end > 0 && depth == 0).then(|| (Self::unescape(&tokens[..end]), end))
+    }
+
+    /// Parses a `"title"`, `'title'` or `(title)` link title at the start of
+    /// `tokens`, returning the title and the number of tokens it covers.
+    fn parse_link_title(tokens: &[Lexeme]) -> Option<(String, usize)> {
+        let open = tokens.first()?.text;
+        let close = match open {
+            "\"" | "'" => open,
+            "(" => ")",
+            _ => return None,
+        };
+
+        let mut end = 1;
+        loop {
+            let lexeme = tokens.get(end)?;
+            if lexeme.text == close {
+                break;
+            }
+            if open == "(" && lexeme.text == "(" {
+                return None;
+            }
+            if lexeme.token == Token::Backslash
+                && tokens.get(end + 1).is_some_and(Self::is_escapable)
             {
-                let mut items = vec![trimmed.split_once('.').unwrap().1.trim().to_string()];
-                while let Some(peeked) = lines.peek() {
-                    let trimmed_peek = peeked.trim_start();
-                    if let Some((_, rest)) = trimmed_peek.split_once('.') {
-                        if trimmed_peek.chars().next().unwrap().is_digit(10) {
-                            lines.next();
-                            items.push(rest.trim().to_string());
-                            continue;
-                        }
-                    }
-                    break;
+                end += 1;
+            }
+            end += 1;
+        }
+
+        Some((Self::unescape(&tokens[1..end]), end + 1))
+    }
+
+    /// Parses a `[text](url "title")` or `![alt](url "title")` construct at
+    /// the start of `tokens`.
+    fn parse_link<'a>(tokens: Line<'a>) -> Option<LinkSyntax<'a>> {
+        let is_image = Self::starts_with(tokens, &[Token::ExclamationMark, Token::LeftBracket]);
+        let start = if is_image { 2 } else { 1 };
+        if tokens.get(start - 1)?.token != Token::LeftBracket {
+            return None;
+        }
+
+        let text_end = Self::link_text_end(tokens, start)?;
+        if tokens.get(text_end + 1)?.token != Token::LeftParen {
+            return None;
+        }
+
+        let mut end = Self::skip_whitespace(tokens, text_end + 2);
+        let mut url = String::new();
+        if let Some((destination, len)) = Self::parse_link_destination(&tokens[end..]) {
+            url = destination;
+            end += len;
+        }
+
+        // The title has to be separated from the destination by whitespace
+        let mut title = None;
+        let after_url = Self::skip_whitespace(tokens, end);
+        end = after_url;
+        if after_url > text_end + 2
+            && let Some((text, len)) = Self::parse_link_title(&tokens[after_url..])
+        {
+            title = Some(text);
+            end = Self::skip_whitespace(tokens, after_url + len);
+        }
+
+        if tokens.get(end)?.token != Token::RightParen {
+            return None;
+        }
+
+        Some(LinkSyntax {
+            is_image,
+            text: &tokens[start..text_end],
+            url,
+            title,
+            len: end + 1,
+        })
+    }
+
+    /// Parses a `[text][label]`, collapsed `[label][]` or shortcut `[label]`
+    /// reference link at the start of `tokens`, which is only a link when the
+    /// document defines its label.
+    fn parse_reference_link<'a>(&self, tokens: Line<'a>) -> Option<LinkSyntax<'a>> {
+        if self.definitions.is_empty() {
+            return None;
+        }
+
+        let is_image = Self::starts_with(tokens, &[Token::ExclamationMark, Token::LeftBracket]);
+        let start = if is_image { 2 } else { 1 };
+        if tokens.get(start - 1)?.token != Token::LeftBracket {
+            return None;
+        }
+
+        let text_end = Self::link_text_end(tokens, start)?;
+        let text = &tokens[start..text_end];
+        let (label, len) = match tokens.get(text_end + 1) {
+            Some(next) if next.token == Token::LeftBracket => {
+                let label_end = Self::label_end(tokens, text_end + 2)?;
+                match label_end == text_end + 2 {
+                    true => (text, label_end + 1),
+                    false => (&tokens[text_end + 2..label_end], label_end + 1),
                 }
-                nodes.push(MarkdownNodes::List(MarkdownList {
-                    ty: MarkdownListType::Numbered,
-                    items,
-                }));
-            } else if trimmed.starts_with("![") || trimmed.starts_with("[") {
-                // Parse link with proper markdown syntax
-                let is_image = trimmed.starts_with("![");
-                let start_pos = if is_image { 2 } else { 1 };
-
-                if let Some(text_end) = trimmed[start_pos..].find(']') {
-                    let alt_text = &trimmed[start_pos..start_pos + text_end];
-                    let remaining = &trimmed[start_pos + text_end + 1..];
-
-                    if remaining.starts_with('(') {
-                        if let Some(url_end) = remaining.find(')') {
-                            let url = &remaining[1..url_end];
-                            nodes.push(MarkdownNodes::Link(MarkdownLink {
-                                alt: alt_text.to_string(),
-                                url: url.to_string(),
-                                is_image,
-                            }));
-                        }
-                    }
+            }
+            _ => (text, text_end + 1),
+        };
+
+        let definition = self
+            .definitions
+            .get(&Self::normalize_label(&Self::text_of(label)))?;
+
+        Some(LinkSyntax {
+            is_image,
+            text,
+            url: definition.url.clone(),
+            title: definition.title.clone(),
+            len,
+        })
+    }
+
+    /// Parses an inline or reference link at the start of `tokens`.
+    fn parse_any_link<'a>(&self, tokens: Line<'a>) -> Option<LinkSyntax<'a>> {
+        Self::parse_link(tokens).or_else(|| self.parse_reference_link(tokens))
+    }
+
+    /// Parses a `[label]: url "title"` link reference definition at the start
+    /// of `tokens`, returning its normalized label, the definition and the
+    /// number of tokens it covers.
+    fn parse_link_definition(tokens: &[Lexeme]) -> Option<(String, LinkDefinition, usize)> {
+        if tokens.first()?.token != Token::LeftBracket {
+            return None;
+        }
+        let label_end = Self::label_end(tokens, 1)?;
+        let label = Self::text_of(&tokens[1..label_end]);
+        // `[^label]:` defines a footnote instead
+        if label.trim().is_empty()
+            || label.starts_with('^')
+            || tokens.get(label_end + 1)?.token != Token::Colon
+        {
+            return None;
+        }
+
+        let start = Self::skip_whitespace(tokens, label_end + 2);
+        let (url, len) = Self::parse_link_destination(&tokens[start..])?;
+        let url_end = start + len;
+
+        // Nothing but the optional title may follow the destination
+        let line_end = |from: usize| {
+            let end = from
+                + tokens[from..]
+                    .iter()
+                    .take_while(|lexeme| lexeme.is_blank())
+                    .count();
+            tokens
+                .get(end)
+                .is_none_or(|lexeme| lexeme.token == Token::Newline)
+                .then_some(end)
+        };
+
+        let after_url = Self::skip_whitespace(tokens, url_end);
+        if after_url > url_end
+            && let Some((title, len)) = Self::parse_link_title(&tokens[after_url..])
+            && let Some(end) = line_end(after_url + len)
+        {
+            let definition = LinkDefinition {
+                url,
+                title: Some(title),
+            };
+            return Some((Self::normalize_label(&label), definition, end));
+        }
+
+        let end = line_end(url_end)?;
+        let definition = LinkDefinition { url, title: None };
+        Some((Self::normalize_label(&label), definition, end))
+    }
+
+    /// Parses a link reference definition starting on `line`, which may carry
+    /// on to the line after it. Returns the definition and how many lines it
+    /// takes up.
+    fn parse_link_definition_lines(
+        line: Line,
+        next: Option<Line>,
+    ) -> Option<(String, LinkDefinition, usize)> {
+        if line.first()?.token != Token::LeftBracket {
+            return None;
+        }
+
+        let mut tokens = line.to_vec();
+        if let Some(next) = next {
+            tokens.push(Lexeme {
+                token: Token::Newline,
+                text: "\n",
+                offset: line.last().map_or(0, Lexeme::end),
+            });
+            tokens.extend_from_slice(Self::trim_start(next));
+        }
+
+        let (label, definition, len) = Self::parse_link_definition(&tokens)?;
+        Some((label, definition, if len > line.len() { 2 } else { 1 }))
+    }
+
+    /// Strips the quote markers and a list marker from the start of a line,
+    /// returning what's left and whether a list item starts on it.
+    fn strip_container_markers<'a>(line: Line<'a>) -> (Line<'a>, bool) {
+        let mut content = line;
+        while let Some(rest) = Self::strip_quote_marker(content) {
+            content = rest;
+        }
+
+        match Self::parse_list_marker(content) {
+            Some(marker) => (Self::trim_start(marker.content), true),
+            None => (Self::trim_start(content), false),
+        }
+    }
+
+    /// Collects the link reference definitions of the whole document before
+    /// it is parsed, so links can refer to definitions further down. Like in
+    /// the block parser, a definition can't start in the middle of a
+    /// paragraph, and the first definition of a label wins.
+    fn collect_link_definitions(tokens: &[Lexeme]) -> HashMap<String, LinkDefinition> {
+        let lines = Self::split_lines(tokens);
+        let mut definitions = HashMap::new();
+        let mut in_fence = false;
+        let mut in_paragraph = false;
+        let mut i = 0;
+
+        while let Some(&line) = lines.get(i) {
+            i += 1;
+            let (content, starts_item) = Self::strip_container_markers(line);
+
+            if in_fence {
+                in_fence = !Self::is_closing_fence(content);
+                continue;
+            }
+            if starts_item {
+                in_paragraph = false;
+            }
+            if Self::starts_with(content, &[Token::Backtick; 3]) {
+                in_fence = true;
+                in_paragraph = false;
+                continue;
+            }
+            if Self::trim(content).is_empty() || Self::starts_block(content) {
+                in_paragraph = false;
+                continue;
+            }
+
+            if !in_paragraph {
+                let next = lines
+                    .get(i)
+                    .map(|&next| Self::strip_container_markers(next).0);
+                if let Some((label, definition, used)) =
+                    Self::parse_link_definition_lines(content, next)
+                {
+                    definitions.entry(label).or_insert(definition);
+                    i += used - 1;
+                    continue;
                 }
-            } else if !trimmed.is_empty() {
-                let spans = self.parse_inline_formatting(trimmed);
-                nodes.push(MarkdownNodes::Paragraph(MarkdownParagraph { spans }));
             }
+            in_paragraph = true;
         }
 
-        nodes
+        definitions
+    }
+
+    /// Parses a `[^label]` footnote reference at the start of `tokens`,
+    /// returning the label and the number of tokens it covers.
+    fn parse_footnote_reference(tokens: &[Lexeme]) -> Option<(String, usize)> {
+        if tokens.first()?.token != Token::LeftBracket || tokens.get(1)?.text != "^" {
+            return None;
+        }
+        // Labels can't contain whitespace or brackets, so stop at the first
+        let end = 2 + tokens[2..].iter().position(|lexeme| {
+            lexeme.is_whitespace()
+                || matches!(lexeme.token, Token::LeftBracket | Token::RightBracket)
+        })?;
+        let label = &tokens[2..end];
+        if label.is_empty() || tokens[end].token != Token::RightBracket {
+            return None;
+        }
+
+        Some((Self::text_of(label), end + 1))
+    }
+
+    /// Parses the `[^label]:` starting a footnote definition, returning the
+    /// label and the rest of the line.
+    fn parse_footnote_definition<'a>(line: Line<'a>) -> Option<(String, Line<'a>)> {
+        let (label, len) = Self::parse_footnote_reference(line)?;
+        if line.get(len)?.token != Token::Colon {
+            return None;
+        }
+
+        Some((label, Self::trim_start(&line[len + 1..])))
+    }
+
+    /// Parses a `<scheme:...>` or `<user@host>` autolink at the start of
+    /// `tokens`, returning the destination and the number of tokens it covers.
+    fn parse_autolink(tokens: &[Lexeme]) -> Option<(String, usize)> {
+        let end = tokens
+            .iter()
+            .position(|lexeme| lexeme.token == Token::GreaterThan)?;
+        let inner = &tokens[1..end];
+        if inner.is_empty()
+            || inner
+                .iter()
+                .any(|lexeme| lexeme.is_whitespace() || lexeme.token == Token::LessThan)
+        {
+            return None;
+        }
+
+        let inner = Self::text_of(inner);
+        let is_uri = inner.split_once(':').is_some_and(|(scheme, _)| {
+            (2..=32).contains(&scheme.len())
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+        });
+        let is_email = inner
+            .split_once('@')
+            .is_some_and(|(user, host)| !user.is_empty() && host.contains('.'));
+
+        (is_uri || is_email).then_some((inner, end + 1))
+    }
+
+    /// Whether a character counts as punctuation next to a delimiter run.
+    /// Non-ASCII characters that are neither alphanumeric nor whitespace are
+    /// treated as punctuation, approximating the Unicode categories of the spec.
+    fn is_punctuation(c: char) -> bool {
+        c.is_ascii_punctuation() || !(c.is_ascii() || c.is_alphanumeric() || c.is_whitespace())
+    }
+
+    /// Reads the run of delimiter characters starting at `tokens[i]`, working
+    /// out from the characters around it whether it can open or close a span.
+    fn parse_delimiter_run(tokens: &[Lexeme], i: usize) -> Delimiter {
+        let text = tokens[i].text;
+        let length = tokens[i..]
+            .iter()
+            .take_while(|lexeme| lexeme.text == text)
+            .count();
+        let before = i
+            .checked_sub(1)
+            .and_then(|previous| tokens[previous].text.chars().next_back());
+        let after = tokens
+            .get(i + length)
+            .and_then(|next| next.text.chars().next());
+
+        // The start and end of the text count as whitespace
+        let space_before = before.is_none_or(char::is_whitespace);
+        let space_after = after.is_none_or(char::is_whitespace);
+        let punctuation_before = before.is_some_and(Self::is_punctuation);
+        let punctuation_after = after.is_some_and(Self::is_punctuation);
+
+        let left_flanking =
+            !space_after && (!punctuation_after || space_before || punctuation_before);
+        let right_flanking =
+            !space_before && (!punctuation_before || space_after || punctuation_after);
+
+        let character = text.chars().next().unwrap_or_default();
+        let (can_open, can_close) = match character {
+            // Unlike the others, `_` doesn't open or close emphasis inside a word
+            '_' => (
+                left_flanking && (!right_flanking || punctuation_before),
+                right_flanking && (!left_flanking || punctuation_after),
+            ),
+            _ => (left_flanking, right_flanking),
+        };
+
+        Delimiter {
+            character,
+            count: length,
+            length,
+            can_open,
+            can_close,
+            start: tokens[i].offset,
+            end: tokens[i + length - 1].end(),
+        }
+    }
+
+    /// Splits a run of tokens, which may span several lines joined by newline
+    /// tokens, into spans and the delimiter runs between them.
+    fn collect_inlines(&self, tokens: &[Lexeme]) -> Vec<Inline> {
+        let mut items = Vec::new();
+        let mut buffer = String::new();
+        // Where the text collected in `buffer` starts and ends in the input
+        let mut range: Option<(usize, usize)> = None;
+
+        let extend = |range: &mut Option<(usize, usize)>, lexeme: &Lexeme| {
+            *range = Some((
+                range.map_or(lexeme.offset, |(start, _)| start),
+                lexeme.end(),
+            ));
+        };
+
+        let flush =
+            |items: &mut Vec<Inline>, buffer: &mut String, range: &mut Option<(usize, usize)>| {
+                if let Some((start, end)) = range.take()
+                    && !buffer.is_empty()
+                {
+                    items.push(Inline::Span(MarkdownSpan {
+                        content: std::mem::take(buffer),
+                        variant: MarkdownVariant::Normal,
+                        children: Vec::new(),
+                        span: self.span(start, end),
+                    }));
+                }
+            };
+
+        // Ends the text before a hard break, which starts at the whitespace
+        // before `tokens[marker]`, and adds the break
+        let line_break = |items: &mut Vec<Inline>,
+                          buffer: &mut String,
+                          range: &mut Option<(usize, usize)>,
+                          marker: usize,
+                          newline: &Lexeme| {
+            buffer.truncate(buffer.trim_end_matches([' ', '\t']).len());
+
+            let mut start = marker;
+            while start > 0 && tokens[start - 1].is_blank() {
+                start -= 1;
+            }
+            let break_start = tokens[start].offset;
+            if let Some((_, end)) = range {
+                *end = (*end).min(break_start);
+            }
+
+            flush(items, buffer, range);
+            items.push(Inline::Span(MarkdownSpan {
+                content: String::new(),
+                variant: MarkdownVariant::LineBreak,
+                children: Vec::new(),
+                span: self.span(break_start, newline.offset),
+            }));
+        };
+
+        let mut i = 0;
+        while let Some(lexeme) = tokens.get(i) {
+            let rest = &tokens[i..];
+
+            i += match lexeme.token {
+                _ if self.is_delimiter(lexeme) => {
+                    flush(&mut items, &mut buffer, &mut range);
+                    let delimiter = Self::parse_delimiter_run(tokens, i);
+                    items.push(Inline::Delimiter(delimiter));
+                    delimiter.length
+                }
+                Token::Backtick => match Self::parse_code_span(rest) {
+                    Some((code, len)) => {
+                        flush(&mut items, &mut buffer, &mut range);
+                        items.push(Inline::Span(MarkdownSpan {
+                            content: code,
+                            variant: MarkdownVariant::InlineCode,
+                            children: Vec::new(),
+                            span: self.span_of(&rest[..len]),
+                        }));
+                        len
+                    }
+                    // A run of backticks that is never closed is text
+                    None => {
+                        let len = Self::backtick_run(rest);
+                        for lexeme in &rest[..len] {
+                            buffer.push_str(lexeme.text);
+                            extend(&mut range, lexeme);
+                        }
+                        len
+                    }
+                },
+                Token::Backslash => match rest.get(1) {
+                    Some(next) if Self::is_escapable(next) => {
+                        buffer.push_str(next.text);
+                        extend(&mut range, lexeme);
+                        extend(&mut range, next);
+                        2
+                    }
+                    Some(next) if next.token == Token::Newline => {
+                        line_break(&mut items, &mut buffer, &mut range, i, next);
+                        2
+                    }
+                    _ => {
+                        buffer.push_str(lexeme.text);
+                        extend(&mut range, lexeme);
+                        1
+                    }
+                },
+                Token::Punctuation if lexeme.text == "&" => match Self::parse_entity(rest) {
+                    Some((entity, len)) => {
+                        buffer.push_str(&entity);
+                        extend(&mut range, lexeme);
+                        extend(&mut range, &rest[len - 1]);
+                        len
+                    }
+                    None => {
+                        buffer.push_str(lexeme.text);
+                        extend(&mut range, lexeme);
+                        1
+                    }
+                },
+                Token::LeftBracket | Token::ExclamationMark | Token::LessThan => {
+                    let footnote = Self::parse_footnote_reference(rest).map(|(label, len)| {
+                        let variant = MarkdownVariant::FootnoteReference(label);
+                        (String::new(), Vec::new(), variant, len)
+                    });
+                    let link = match lexeme.token {
+                        Token::LessThan => Self::parse_autolink(rest)
+                            .map(|(url, len)| (url, Vec::new(), MarkdownVariant::AutoLink, len)),
+                        _ if footnote.is_some() => footnote,
+                        _ => self.parse_any_link(rest).map(|link| {
+                            let (url, title) = (link.url, link.title);
+                            let variant = match link.is_image {
+                                true => MarkdownVariant::Image { url, title },
+                                false => MarkdownVariant::Link { url, title },
+                            };
+                            let children = self.parse_inline_formatting(link.text);
+                            (String::new(), children, variant, link.len)
+                        }),
+                    };
+
+                    let Some((content, children, variant, len)) = link else {
+                        buffer.push_str(lexeme.text);
+                        extend(&mut range, lexeme);
+                        i += 1;
+                        continue;
+                    };
+
+                    flush(&mut items, &mut buffer, &mut range);
+                    items.push(Inline::Span(MarkdownSpan {
+                        content,
+                        variant,
+                        children,
+                        span: self.span_of(&rest[..len]),
+                    }));
+                    len
+                }
+                Token::Newline if buffer.ends_with("  ") => {
+                    line_break(&mut items, &mut buffer, &mut range, i, lexeme);
+                    1
+                }
+                Token::Newline => {
+                    buffer.truncate(buffer.trim_end_matches([' ', '\t']).len());
+                    buffer.push('\n');
+                    extend(&mut range, lexeme);
+                    1
+                }
+                _ => {
+                    buffer.push_str(lexeme.text);
+                    extend(&mut range, lexeme);
+                    1
+                }
+            };
+        }
+
+        flush(&mut items, &mut buffer, &mut range);
+
+        items
+    }
+
+    /// Whether a token starts a delimiter run, which for the characters of
+    /// extensions depends on whether they are enabled.
+    fn is_delimiter(&self, lexeme: &Lexeme) -> bool {
+        match lexeme.token {
+            Token::Asterisk | Token::Underscore => true,
+            Token::Tilde => self.extensions.strikethrough || self.extensions.subscript,
+            Token::Equals => self.extensions.highlight,
+            Token::Punctuation => lexeme.text == "^" && self.extensions.superscript,
+            _ => false,
+        }
+    }
+
+    /// Whether `opener` can be closed by `closer`, and the variant of the span
+    /// they make along with how many characters of each run it uses.
+    ///
+    /// For emphasis, when either run can both open and close, their lengths
+    /// must not add up to a multiple of three unless both are multiples of
+    /// three, so `*foo**bar*` stays a single emphasis. The other delimiters
+    /// only match runs of the same length, and superscript and subscript
+    /// can't contain whitespace, so `x^2 + y^2` stays plain text.
+    fn closes(&self, opener: &Delimiter, closer: &Delimiter) -> Option<(MarkdownVariant, usize)> {
+        if opener.character != closer.character || !opener.can_open {
+            return None;
+        }
+
+        let same_length = opener.count == closer.count;
+        let no_whitespace = || {
+            self.input
+                .get(opener.end..closer.start)
+                .is_some_and(|text| !text.contains(char::is_whitespace))
+        };
+
+        match opener.character {
+            '*' | '_' => {
+                let rule_of_three = !(opener.can_close || closer.can_open)
+                    || !(opener.length + closer.length).is_multiple_of(3)
+                    || (opener.length.is_multiple_of(3) && closer.length.is_multiple_of(3));
+                // Strong emphasis takes two characters from each run when it can
+                let strong = opener.count >= 2 && closer.count >= 2;
+                rule_of_three.then_some(match strong {
+                    true => (MarkdownVariant::Bold, 2),
+                    false => (MarkdownVariant::Italic, 1),
+                })
+            }
+            '~' if same_length && opener.count == 2 && self.extensions.strikethrough => {
+                Some((MarkdownVariant::Strikethrough, 2))
+            }
+            '~' if same_length && opener.count == 1 && self.extensions.subscript => {
+                no_whitespace().then_some((MarkdownVariant::Subscript, 1))
+            }
+            '=' if same_length && opener.count == 2 && self.extensions.highlight => {
+                Some((MarkdownVariant::Highlight, 2))
+            }
+            '^' if same_length && opener.count == 1 && self.extensions.superscript => {
+                no_whitespace().then_some((MarkdownVariant::Superscript, 1))
+            }
+            _ => None,
+        }
+    }
+
+    /// Pairs up delimiter runs into spans, working through the closers from
+    /// left to right and matching each with the nearest opener before it. The
+    /// items between a pair become the children of the span, and runs that
+    /// are never matched are left in place.
+    ///
+    /// Like the CommonMark reference algorithm, items are moved onto a stack
+    /// as they are passed, and it remembers how far down the stack the search
+    /// for an opener failed for each kind of closer, so later closers of that
+    /// kind don't search the same items again. Every item is searched and
+    /// moved into a span at most once per kind of closer, keeping this linear.
+    fn match_emphasis(&self, items: &mut Vec<Inline>) {
+        // The lowest item on the stack an opener for a kind of closer can be
+        let mut openers_bottom: HashMap<(char, bool, usize), usize> = HashMap::new();
+        let mut stack = Vec::with_capacity(items.len());
+
+        for item in items.drain(..) {
+            let Inline::Delimiter(mut close) = item else {
+                stack.push(item);
+                continue;
+            };
+
+            // Keep matching the same closer while it has characters left
+            while close.count > 0 {
+                // Whether an opener matches depends on these properties of the
+                // closer, see `closes`
+                let kind = match close.is_emphasis() {
+                    true => (close.character, close.can_open, close.length % 3),
+                    false => (close.character, close.can_open, close.count),
+                };
+                let bottom = openers_bottom.get(&kind).copied().unwrap_or(0);
+                let opener =
+                    match close.can_close {
+                        true => {
+                            stack[bottom..].iter().enumerate().rev().find_map(
+                                |(i, item)| match item {
+                                    Inline::Delimiter(open) => self
+                                        .closes(open, &close)
+                                        .map(|(variant, used)| (bottom + i, *open, variant, used)),
+                                    _ => None,
+                                },
+                            )
+                        }
+                        false => None,
+                    };
+                let Some((opener, mut open, variant, used)) = opener else {
+                    // The closer itself may still open a later span
+                    if close.can_close {
+                        openers_bottom.insert(kind, stack.len());
+                    }
+                    stack.push(Inline::Delimiter(close));
+                    break;
+                };
+
+                open.count -= used;
+                open.end -= used;
+                close.count -= used;
+                close.start += used;
+
+                // Runs between the pair can no longer match and become text
+                let children = self.finish_spans(stack.drain(opener + 1..));
+                if open.count == 0 {
+                    stack.pop();
+                } else {
+                    stack[opener] = Inline::Delimiter(open);
+                }
+
+                // Searches that stopped among the items now inside the span
+                // stop at the span instead
+                for bottom in openers_bottom.values_mut() {
+                    *bottom = (*bottom).min(stack.len());
+                }
+
+                stack.push(Inline::Span(MarkdownSpan {
+                    content: String::new(),
+                    variant,
+                    children,
+                    span: self.span(open.end, close.start),
+                }));
+            }
+        }
+
+        *items = stack;
+    }
+
+    /// Turns inline items into spans, keeping unmatched delimiter runs as text
+    /// and merging adjacent text.
+    fn finish_spans(&self, items: impl IntoIterator<Item = Inline>) -> Vec<MarkdownSpan> {
+        let mut spans: Vec<MarkdownSpan> = Vec::new();
+
+        for item in items {
+            let span = match item {
+                Inline::Span(span) => span,
+                Inline::Delimiter(delimiter) => MarkdownSpan {
+                    content: delimiter.text(),
+                    variant: MarkdownVariant::Normal,
+                    children: Vec::new(),
+                    span: self.span(delimiter.start, delimiter.end),
+                },
+            };
+
+            match spans.last_mut() {
+                Some(last)
+                    if matches!(last.variant, MarkdownVariant::Normal)
+                        && matches!(span.variant, MarkdownVariant::Normal) =>
+                {
+                    last.content.push_str(&span.content);
+                    last.span.end = span.span.end;
+                }
+                _ => spans.push(span),
+            }
+        }
+
+        spans
+    }
+
+    /// Parses the inline formatting of a run of tokens, which may span several
+    /// lines joined by newline tokens.
+    fn parse_inline_formatting(&self, tokens: &[Lexeme]) -> Vec<MarkdownSpan> {
+        let mut items = self.collect_inlines(tokens);
+        self.match_emphasis(&mut items);
+        self.finish_spans(items)
+    }
+
+    /// Strips a single blockquote marker (`>` and one optional space) from a line.
+    fn strip_quote_marker<'a>(line: Line<'a>) -> Option<Line<'a>> {
+        let (marker, rest) = Self::trim_start(line).split_first()?;
+        if marker.token != Token::GreaterThan {
+            return None;
+        }
+
+        Some(match rest.split_first() {
+            Some((space, rest)) if space.token == Token::Space => rest,
+            _ => rest,
+        })
+    }
+
+    /// Whether a line starts a block construct that interrupts a paragraph.
+    fn starts_block(line: Line) -> bool {
+        let trimmed = Self::trim_start(line);
+
+        Self::strip_quote_marker(line).is_some()
+            || Self::starts_with(trimmed, &[Token::Backtick; 3])
+            || Self::starts_with(trimmed, &[Token::Dash; 3])
+            || Self::parse_atx_heading(trimmed).is_some()
+            || Self::parse_list_marker(line).is_some_and(|marker| !marker.content.is_empty())
+            || Self::parse_footnote_definition(trimmed).is_some()
+    }
+
+    /// Whether a line is the ```` ``` ```` that closes a code block.
+    fn is_closing_fence(line: Line) -> bool {
+        Self::trim(line)
+            .iter()
+            .map(|lexeme| lexeme.token)
+            .eq([Token::Backtick; 3])
+    }
+
+    fn indent_of(line: Line) -> usize {
+        line.iter().take_while(|lexeme| lexeme.is_blank()).count()
+    }
+
+    /// Parses a bulleted (`-`, `*`, `+`) or numbered (`1.`, `1)`) list marker.
+    fn parse_list_marker<'a>(line: Line<'a>) -> Option<ListMarker<'a>> {
+        let indent = Self::indent_of(line);
+        let trimmed = &line[indent..];
+
+        let (ty, number, marker_tokens, marker_len) = match trimmed {
+            [bullet, ..] if matches!(bullet.token, Token::Dash | Token::Asterisk | Token::Plus) => {
+                (MarkdownListType::Bulleted, None, 1, 1)
+            }
+            [number, delimiter, ..]
+                if number.token == Token::Number
+                    && number.text.len() <= 9
+                    && matches!(delimiter.token, Token::Period | Token::RightParen) =>
+            {
+                let len = number.text.len();
+                (
+                    MarkdownListType::Numbered,
+                    number.text.parse().ok(),
+                    2,
+                    len + 1,
+                )
+            }
+            _ => return None,
+        };
+
+        let rest = &trimmed[marker_tokens..];
+        let spaces = rest
+            .iter()
+            .take_while(|lexeme| lexeme.token == Token::Space)
+            .count();
+        let content = &rest[spaces..];
+        if spaces == 0 && !content.is_empty() {
+            return None;
+        }
+
+        Some(ListMarker {
+            ty,
+            number,
+            content_offset: indent + marker_len + spaces.clamp(1, 4),
+            content,
+        })
+    }
+
+    /// Parses a `[ ]` or `[x]` task marker at the start of a list item's
+    /// content, returning whether it is checked and the content after it.
+    fn parse_task_marker<'a>(content: Line<'a>) -> Option<(bool, Line<'a>)> {
+        let [open, mark, close, rest @ ..] = content else {
+            return None;
+        };
+        if open.token != Token::LeftBracket || close.token != Token::RightBracket {
+            return None;
+        }
+        let checked = match mark.text {
+            " " => false,
+            "x" | "X" => true,
+            _ => return None,
+        };
+
+        // Like a list marker, the task marker must be followed by whitespace
+        match rest.first() {
+            Some(next) if !next.is_blank() => None,
+            _ => Some((checked, Self::trim_start(rest))),
+        }
+    }
+
+    /// Parses an ATX heading (`#` to `######` followed by a space), returning its
+    /// level and content with any closing `#` sequence removed.
+    fn parse_atx_heading<'a>(line: Line<'a>) -> Option<(u8, Line<'a>)> {
+        let hashes = line
+            .iter()
+            .take_while(|lexeme| lexeme.token == Token::Hash)
+            .count();
+        if hashes == 0 || hashes > 6 {
+            return None;
+        }
+
+        let rest = &line[hashes..];
+        if rest.first().is_some_and(|lexeme| !lexeme.is_blank()) {
+            return None;
+        }
+
+        let content = Self::trim(rest);
+        let closing = content
+            .iter()
+            .rev()
+            .take_while(|lexeme| lexeme.token == Token::Hash)
+            .count();
+        let without_closing = &content[..content.len() - closing];
+        let content = if without_closing.is_empty() {
+            without_closing
+        } else if without_closing.last().is_some_and(Lexeme::is_blank) {
+            Self::trim_end(without_closing)
+        } else {
+            content
+        };
+
+        Some((hashes as u8, content))
+    }
+
+    /// Parses a setext heading underline (`===` for level 1, `---` for level 2).
+    fn parse_setext_underline(line: Line) -> Option<u8> {
+        if Self::indent_of(line) > 3 {
+            return None;
+        }
+
+        let trimmed = Self::trim(line);
+        if Self::is_only(trimmed, Token::Equals) {
+            Some(1)
+        } else if Self::is_only(trimmed, Token::Dash) {
+            Some(2)
+        } else {
+            None
+        }
+    }
+
+    /// Splits a table row into the tokens of each cell, honouring `\|` escapes.
+    fn split_table_row<'a>(line: Line<'a>) -> Vec<Vec<Lexeme<'a>>> {
+        let trimmed = Self::trim(line);
+        let trimmed = match trimmed.split_first() {
+            Some((first, rest)) if first.token == Token::Pipe => rest,
+            _ => trimmed,
+        };
+        let trimmed = match trimmed.split_last() {
+            Some((last, rest))
+                if last.token == Token::Pipe
+                    && rest.last().is_none_or(|l| l.token != Token::Backslash) =>
+            {
+                rest
+            }
+            _ => trimmed,
+        };
+
+        let mut cells = Vec::new();
+        let mut cell = Vec::new();
+        let mut tokens = trimmed.iter().peekable();
+
+        while let Some(lexeme) = tokens.next() {
+            match lexeme.token {
+                Token::Backslash => match tokens.next_if(|next| next.token == Token::Pipe) {
+                    Some(pipe) => cell.push(*pipe),
+                    None => cell.push(*lexeme),
+                },
+                Token::Pipe => cells.push(Self::trim(&std::mem::take(&mut cell)).to_vec()),
+                _ => cell.push(*lexeme),
+            }
+        }
+        cells.push(Self::trim(&cell).to_vec());
+
+        cells
+    }
+
+    /// Parses a delimiter row such as `|:---|---:|` into column alignments.
+    fn parse_table_delimiter(line: Line) -> Option<Vec<MarkdownAlignment>> {
+        if !Self::contains(line, Token::Dash) {
+            return None;
+        }
+
+        Self::split_table_row(line)
+            .iter()
+            .map(|cell| {
+                let left = cell.first().is_some_and(|l| l.token == Token::Colon);
+                let right = cell.last().is_some_and(|l| l.token == Token::Colon);
+
+                let start = cell.iter().take_while(|l| l.token == Token::Colon).count();
+                let end = cell.len()
+                    - cell[start..]
+                        .iter()
+                        .rev()
+                        .take_while(|l| l.token == Token::Colon)
+                        .count();
+                if !Self::is_only(&cell[start..end], Token::Dash) {
+                    return None;
+                }
+
+                Some(match (left, right) {
+                    (true, true) => MarkdownAlignment::Center,
+                    (true, false) => MarkdownAlignment::Left,
+                    (false, true) => MarkdownAlignment::Right,
+                    (false, false) => MarkdownAlignment::None,
+                })
+            })
+            .collect()
+    }
+
+    fn parse_table_cells(&self, line: Line, columns: usize) -> Vec<Vec<MarkdownSpan>> {
+        let mut cells = Self::split_table_row(line);
+        cells.resize(columns, Vec::new());
+        cells
+            .iter()
+            .map(|cell| self.parse_inline_formatting(cell))
+            .collect()
+    }
+
+    pub fn parse(&self) -> Vec<MarkdownNodes> {
+        let tokens = Self::lex(&self.input);
+        self.parse_blocks(&Self::split_lines(&tokens))
+    }
+
+    /// Parses the blocks of `lines`, which are either the lines of the input or
+    /// what is left of them inside a blockquote or list item.
+    fn parse_blocks(&self, lines: &[Line]) -> Vec<MarkdownNodes> {
+        let mut nodes = Vec::new();
+        let mut lines = lines.iter().copied().peekable();
+
+        while let Some(line) = lines.next() {
+            let trimmed = Self::trim_start(line);
+            let Some(first) = trimmed.first() else {
+                continue;
+            };
+            let start = first.offset;
+            let line_end = Self::end_of(line).unwrap_or(start);
+
+            if let Some(quoted) = Self::strip_quote_marker(line) {
+                let mut inner = vec![quoted];
+                let mut end = line_end;
+                let mut lazy = LazyContinuation::default();
+                lazy.push(quoted);
+                // Quoted lines continue the quote, and so does unmarked text
+                // while the quote ends in a paragraph
+                while let Some(next) = lines.peek().copied() {
+                    let quoted = match Self::strip_quote_marker(next) {
+                        Some(quoted) => quoted,
+                        None if lazy.accepts(next) => Self::trim_start(next),
+                        None => break,
+                    };
+                    inner.push(quoted);
+                    lazy.push(quoted);
+                    end = Self::end_of(next).unwrap_or(end);
+                    lines.next();
+                }
+                nodes.push(MarkdownNodes::BlockQuote(MarkdownBlockQuote {
+                    children: self.parse_blocks(&inner),
+                    span: self.span(start, end),
+                }));
+            } else if Self::starts_with(trimmed, &[Token::Dash; 3]) {
+                nodes.push(MarkdownNodes::HorizontalRule(self.span(start, line_end)))
+            } else if let Some((level, content)) = Self::parse_atx_heading(trimmed) {
+                nodes.push(MarkdownNodes::Heading(MarkdownHeading {
+                    level,
+                    content: self.parse_inline_formatting(content),
+                    span: self.span(start, line_end),
+                }));
+            } else if Self::starts_with(trimmed, &[Token::Backtick; 3]) {
+                let lang = Self::unescape(Self::trim(&trimmed[3..]));
+                let mut content = String::new();
+                let mut end = line_end;
+                for next in lines.by_ref() {
+                    end = Self::end_of(next).unwrap_or(end);
+                    if Self::is_closing_fence(next) {
+                        break;
+                    } else {
+                        content.push_str(&Self::text_of(next));
+                        content.push('\n');
+                    }
+                }
+                nodes.push(MarkdownNodes::CodeBlock(MarkdownCodeBlock {
+                    language: lang,
+                    content,
+                    span: self.span(start, end),
+                }));
+            } else if let Some(marker) = Self::parse_list_marker(line) {
+                let ty = marker.ty;
+                let number = marker.number;
+                let mut items = Vec::new();
+                let mut marker = marker;
+                let mut loose = false;
+                let mut item_start = start;
+                let mut end = line_end;
+
+                loop {
+                    let (checked, first) = match Self::parse_task_marker(marker.content) {
+                        Some((checked, rest)) => (Some(checked), rest),
+                        None => (None, marker.content),
+                    };
+                    let mut content = vec![first];
+                    let mut lazy = LazyContinuation::default();
+                    lazy.push(first);
+
+                    // Collect every line indented past the marker, including
+                    // blank lines that are followed by more item content, and
+                    // unindented text while the item ends in a paragraph
+                    while let Some(next) = lines.peek().copied() {
+                        if Self::trim(next).is_empty() {
+                            let continues = lines
+                                .clone()
+                                .find(|l| !Self::trim(l).is_empty())
+                                .is_some_and(|l| Self::indent_of(l) >= marker.content_offset);
+                            if !continues {
+                                break;
+                            }
+                            content.push(&[]);
+                            // Blank lines inside a code block don't separate
+                            // children
+                            loose |= !lazy.in_fence;
+                        } else if Self::indent_of(next) >= marker.content_offset {
+                            content.push(&next[marker.content_offset..]);
+                            end = Self::end_of(next).unwrap_or(end);
+                        } else if lazy.accepts(next) {
+                            content.push(Self::trim_start(next));
+                            end = Self::end_of(next).unwrap_or(end);
+                        } else {
+                            break;
+                        }
+                        lazy.push(content[content.len() - 1]);
+                        lines.next();
+                    }
+
+                    items.push(MarkdownListItem {
+                        children: self.parse_blocks(&content),
+                        checked,
+                        span: self.span(item_start, end),
+                    });
+
+                    let blank_after = lines.peek().is_some_and(|l| Self::trim(l).is_empty());
+                    let mut ahead = lines.clone();
+                    let sibling = ahead
+                        .find(|l| !Self::trim(l).is_empty())
+                        .and_then(|l| Self::parse_list_marker(l).map(|next| (l, next)))
+                        .filter(|(_, next)| next.ty == ty);
+
+                    match sibling {
+                        Some((next_line, next)) => {
+                            lines = ahead;
+                            marker = next;
+                            loose |= blank_after;
+                            item_start = Self::trim_start(next_line)
+                                .first()
+                                .map_or(end, |l| l.offset);
+                            end = Self::end_of(next_line).unwrap_or(item_start);
+                        }
+                        None => break,
+                    }
+                }
+
+                nodes.push(MarkdownNodes::List(MarkdownList {
+                    ty,
+                    start: number,
+                    loose,
+                    items,
+                    span: self.span(start, end),
+                }));
+            } else if let Some((label, first)) = Self::parse_footnote_definition(trimmed) {
+                let mut content = vec![first];
+                let mut end = line_end;
+                // Text straight after the definition continues it like a
+                // paragraph, and indented lines add more blocks
+                let mut lazy = true;
+
+                while let Some(next) = lines.peek().copied() {
+                    if Self::trim(next).is_empty() {
+                        let continues = lines
+                            .clone()
+                            .find(|l| !Self::trim(l).is_empty())
+                            .is_some_and(|l| Self::indent_of(l) >= FOOTNOTE_INDENT);
+                        if !continues {
+                            break;
+                        }
+                        content.push(&[]);
+                        lazy = false;
+                    } else if Self::indent_of(next) >= FOOTNOTE_INDENT {
+                        content.push(&next[FOOTNOTE_INDENT..]);
+                    } else if lazy && !Self::starts_block(next) {
+                        content.push(Self::trim_start(next));
+                    } else {
+                        break;
+                    }
+                    end = Self::end_of(next).unwrap_or(end);
+                    lines.next();
+                }
+
+                nodes.push(MarkdownNodes::Footnote(MarkdownFootnote {
+                    label,
+                    children: self.parse_blocks(&content),
+                    span: self.span(start, end),
+                }));
+            } else if let Some((_, _, used)) =
+                Self::parse_link_definition_lines(trimmed, lines.peek().copied())
+            {
+                // Definitions were collected up front and render as nothing
+                if used == 2 {
+                    lines.next();
+                }
+            } else if let Some(alignments) = lines
+                .peek()
+                .filter(|_| Self::contains(trimmed, Token::Pipe))
+                .and_then(|next| Self::parse_table_delimiter(next))
+                .filter(|alignments| alignments.len() == Self::split_table_row(trimmed).len())
+            {
+                let mut end = lines.next().and_then(Self::end_of).unwrap_or(line_end);
+                let columns = alignments.len();
+                let header = self.parse_table_cells(trimmed, columns);
+                let mut rows = Vec::new();
+                while let Some(row) =
+                    lines.next_if(|l| !Self::trim(l).is_empty() && Self::contains(l, Token::Pipe))
+                {
+                    rows.push(self.parse_table_cells(row, columns));
+                    end = Self::end_of(row).unwrap_or(end);
+                }
+                nodes.push(MarkdownNodes::Table(MarkdownTable {
+                    alignments,
+                    header,
+                    rows,
+                    span: self.span(start, end),
+                }));
+            } else if let Some(link) = self
+                .parse_any_link(trimmed)
+                .filter(|link| link.len == Self::trim_end(trimmed).len())
+            {
+                nodes.push(MarkdownNodes::Link(MarkdownLink {
+                    alt: self.parse_inline_formatting(link.text),
+                    span: self.span_of(&trimmed[..link.len]),
+                    url: link.url,
+                    title: link.title,
+                    is_image: link.is_image,
+                }));
+            } else {
+                // Consecutive text lines form a single paragraph, unless an
+                // underline turns them into a setext heading
+                let mut text = trimmed.to_vec();
+                let mut end = line_end;
+                let mut heading_level = None;
+                while let Some(next) = lines.peek().copied() {
+                    if let Some(level) = Self::parse_setext_underline(next) {
+                        heading_level = Some(level);
+                        end = Self::end_of(next).unwrap_or(end);
+                        lines.next();
+                        break;
+                    }
+                    if Self::trim(next).is_empty() || Self::starts_block(next) {
+                        break;
+                    }
+                    text.push(Lexeme {
+                        token: Token::Newline,
+                        text: "\n",
+                        offset: text.last().map_or(start, Lexeme::end),
+                    });
+                    text.extend_from_slice(Self::trim_start(next));
+                    end = Self::end_of(next).unwrap_or(end);
+                    lines.next();
+                }
+
+                let spans = self.parse_inline_formatting(Self::trim_end(&text));
+                let span = self.span(start, end);
+                nodes.push(match heading_level {
+                    Some(level) => MarkdownNodes::Heading(MarkdownHeading {
+                        level,
+                        content: spans,
+                        span,
+                    }),
+                    None => MarkdownNodes::Paragraph(MarkdownParagraph { spans, span }),
+                });
+            }
+        }
+
+        nodes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in the markdown source.
+#[derive(Debug)]
+pub struct ParseDiagnostic {
+    pub span: SourceSpan,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl MarkdownParser {
+    /// Scans the source for constructs the parser can't render as intended,
+    /// such as unclosed code fences, emphasis or inline code and links that end
+    /// up as plain text.
+    pub fn diagnose(&self) -> Vec<ParseDiagnostic> {
+        let tokens = Self::lex(&self.input);
+        let nodes = self.parse();
+        let mut diagnostics = Vec::new();
+
+        self.diagnose_nodes(&nodes, &tokens, &mut diagnostics);
+        self.check_footnotes(&nodes, &mut diagnostics);
+
+        diagnostics.sort_by_key(|d| d.span.start.offset);
+        diagnostics
+    }
+
+    /// The tokens a node with the span `span` was parsed from.
+    fn tokens_in<'t>(&self, tokens: Line<'t>, span: SourceSpan) -> Line<'t> {
+        // Spans are relative to the file, tokens to the body the parser got
+        let base = self.span(0, 0).start.offset;
+        let from = tokens.partition_point(|l| l.offset < span.start.offset - base);
+        let to = tokens.partition_point(|l| l.offset < span.end.offset - base);
+        &tokens[from..to]
+    }
+
+    /// Scans the source of every block with inline content, and reports code
+    /// fences that are never closed and blocks that render unlike they read.
+    fn diagnose_nodes(
+        &self,
+        nodes: &[MarkdownNodes],
+        tokens: Line,
+        diagnostics: &mut Vec<ParseDiagnostic>,
+    ) {
+        for node in nodes {
+            match node {
+                MarkdownNodes::Paragraph(MarkdownParagraph { span, .. })
+                | MarkdownNodes::Heading(MarkdownHeading { span, .. })
+                | MarkdownNodes::Link(MarkdownLink { span, .. })
+                | MarkdownNodes::Table(MarkdownTable { span, .. }) => {
+                    self.scan_inline(self.tokens_in(tokens, *span), diagnostics)
+                }
+                MarkdownNodes::CodeBlock(code) => {
+                    let block = self.tokens_in(tokens, code.span);
+                    let lines = Self::split_lines(block);
+                    let closed = lines.len() > 1
+                        && lines.last().is_some_and(|&line| {
+                            let mut line = line;
+                            while let Some(rest) = Self::strip_quote_marker(line) {
+                                line = rest;
+                            }
+                            Self::is_closing_fence(line)
+                        });
+                    if !closed {
+                        diagnostics.push(ParseDiagnostic {
+                            span: self.span_of(&block[..block.len().min(3)]),
+                            severity: Severity::Error,
+                            message: "unclosed code fence, the rest of the file renders as code"
+                                .to_string(),
+                        });
+                    }
+                }
+                MarkdownNodes::List(list) => {
+                    let tasks = list.items.iter().filter(|i| i.checked.is_some()).count();
+                    if tasks > 0 && tasks < list.items.len() {
+                        diagnostics.push(ParseDiagnostic {
+                            span: list.span,
+                            severity: Severity::Warning,
+                            message: "list mixes task items with plain items, only the task items get a checkbox"
+                                .to_string(),
+                        });
+                    }
+                    for item in &list.items {
+                        if item.children.is_empty() && item.checked.is_none() {
+                            diagnostics.push(ParseDiagnostic {
+                                span: item.span,
+                                severity: Severity::Warning,
+                                message: "empty list item, it renders as a bullet without text"
+                                    .to_string(),
+                            });
+                        }
+                        self.diagnose_nodes(&item.children, tokens, diagnostics);
+                    }
+                }
+                MarkdownNodes::BlockQuote(quote) => {
+                    if quote.children.is_empty() {
+                        diagnostics.push(ParseDiagnostic {
+                            span: quote.span,
+                            severity: Severity::Warning,
+                            message: "empty block quote, it renders as an empty box".to_string(),
+                        });
+                    }
+                    self.diagnose_nodes(&quote.children, tokens, diagnostics)
+                }
+                MarkdownNodes::Footnote(footnote) => {
+                    self.diagnose_nodes(&footnote.children, tokens, diagnostics)
+                }
+                MarkdownNodes::HorizontalRule(span) => {
+                    // Front matter that isn't closed, or isn't YAML, is left
+                    // in the page and starts it with a rule
+                    if span.start.line == 1 {
+                        diagnostics.push(ParseDiagnostic {
+                            span: *span,
+                            severity: Severity::Warning,
+                            message: "`---` on the first line renders as a rule, front matter needs `key: value` lines and a closing `---`"
+                                .to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reports references to footnotes that are never defined, and footnotes
+    /// that are never referenced.
+    pub fn check_footnotes(&self, nodes: &[MarkdownNodes], diagnostics: &mut Vec<ParseDiagnostic>) {
+        let mut definitions = Vec::new();
+        let mut references = Vec::new();
+        Self::collect_footnotes(nodes, &mut definitions, &mut references);
+
+        for (label, span) in &references {
+            if !definitions.iter().any(|(defined, _)| defined == label) {
+                diagnostics.push(ParseDiagnostic {
+                    span: *span,
+                    severity: Severity::Warning,
+                    message: format!(
+                        "footnote `[^{}]` is never defined, so it renders as plain text",
+                        label
+                    ),
+                });
+            }
+        }
+
+        for (label, span) in &definitions {
+            if !references.iter().any(|(referenced, _)| referenced == label) {
+                diagnostics.push(ParseDiagnostic {
+                    span: *span,
+                    severity: Severity::Warning,
+                    message: format!(
+                        "footnote `[^{}]` is never referenced, so it isn't rendered",
+                        label
+                    ),
+                });
+            }
+        }
+    }
+
+    /// Collects the labels and spans of footnote definitions and references.
+    fn collect_footnotes<'n>(
+        nodes: &'n [MarkdownNodes],
+        definitions: &mut Vec<(&'n str, SourceSpan)>,
+        references: &mut Vec<(&'n str, SourceSpan)>,
+    ) {
+        for node in nodes {
+            match node {
+                MarkdownNodes::Paragraph(p) => Self::collect_references(&p.spans, references),
+                MarkdownNodes::Heading(h) => Self::collect_references(&h.content, references),
+                MarkdownNodes::Link(l) => Self::collect_references(&l.alt, references),
+                MarkdownNodes::Table(t) => {
+                    for cell in t.header.iter().chain(t.rows.iter().flatten()) {
+                        Self::collect_references(cell, references);
+                    }
+                }
+                MarkdownNodes::List(l) => {
+                    for item in &l.items {
+                        Self::collect_footnotes(&item.children, definitions, references);
+                    }
+                }
+                MarkdownNodes::BlockQuote(q) => {
+                    Self::collect_footnotes(&q.children, definitions, references)
+                }
+                MarkdownNodes::Footnote(f) => {
+                    definitions.push((&f.label, f.span));
+                    Self::collect_footnotes(&f.children, definitions, references);
+                }
+                MarkdownNodes::CodeBlock(_) | MarkdownNodes::HorizontalRule(_) => {}
+            }
+        }
+    }
+
+    fn collect_references<'n>(
+        spans: &'n [MarkdownSpan],
+        references: &mut Vec<(&'n str, SourceSpan)>,
+    ) {
+        for span in spans {
+            if let MarkdownVariant::FootnoteReference(label) = &span.variant {
+                references.push((label, span.span));
+            }
+            Self::collect_references(&span.children, references);
+        }
+    }
+
+    /// Scans the inline content of a block.
+    fn scan_inline(&self, tokens: &[Lexeme], diagnostics: &mut Vec<ParseDiagnostic>) {
+        let mut i = 0;
+
+        while let Some(lexeme) = tokens.get(i) {
+            let rest = &tokens[i..];
+            let line = &rest[..rest
+                .iter()
+                .position(|l| l.token == Token::Newline)
+                .unwrap_or(rest.len())];
+
+            i += match lexeme.token {
+                Token::Backtick => {
+                    if Self::parse_code_span(rest).is_none() {
+                        diagnostics.push(ParseDiagnostic {
+                            span: self.span_of(&rest[..Self::backtick_run(rest)]),
+                            severity: Severity::Warning,
+                            message: "unclosed inline code, the backticks render as literal text"
+                                .to_string(),
+                        });
+                    }
+                    Self::opaque_len(rest)
+                }
+                Token::Backslash => Self::opaque_len(rest),
+                Token::LessThan => Self::parse_autolink(rest).map_or(1, |(_, len)| len),
+                Token::LeftBracket | Token::ExclamationMark => {
+                    if let Some(link) = self.parse_any_link(rest) {
+                        link.len
+                    } else {
+                        if lexeme.token == Token::LeftBracket
+                            && line
+                                .iter()
+                                .position(|l| l.token == Token::RightBracket)
+                                .and_then(|end| line.get(end + 1))
+                                .is_some_and(|l| l.token == Token::LeftParen)
+                        {
+                            diagnostics.push(ParseDiagnostic {
+                                span: self.span_of(line),
+                                severity: Severity::Warning,
+                                message: "malformed link, it is missing a closing `)` or its URL \
+                                          contains whitespace, so it renders as plain text"
+                                    .to_string(),
+                            });
+                        }
+                        1
+                    }
+                }
+                _ => 1,
+            };
+        }
+
+        // A run that can open emphasis but not close it was likely meant to
+        // open one, while other unmatched runs are usually literal text
+        let mut items = self.collect_inlines(tokens);
+        self.match_emphasis(&mut items);
+        for item in items {
+            if let Inline::Delimiter(delimiter) = item
+                && delimiter.is_emphasis()
+                && delimiter.can_open
+                && !delimiter.can_close
+            {
+                diagnostics.push(ParseDiagnostic {
+                    span: self.span(delimiter.start, delimiter.end),
+                    severity: Severity::Warning,
+                    message: format!(
+                        "unclosed `{}` emphasis, it renders as literal text",
+                        delimiter.text()
+                    ),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn diagnose(input: &str) -> Vec<(usize, usize, String)> {
+        MarkdownParser::new(input.to_string())
+            .diagnose()
+            .into_iter()
+            .map(|d| (d.span.start.line, d.span.start.column, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn diagnoses_blocks_at_their_spans() {
+        let diagnostics = diagnose("- ```\n  code\n  ```\n\n> quoted *open\nlazy\n\n> ```\n> code");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].0, 5);
+        assert_eq!(diagnostics[0].1, 10);
+        assert!(diagnostics[0].2.starts_with("unclosed `*` emphasis"));
+        assert_eq!((diagnostics[1].0, diagnostics[1].1), (8, 3));
+        assert!(diagnostics[1].2.starts_with("unclosed code fence"));
+    }
+
+    #[test]
+    fn diagnoses_lists_quotes_and_rules_at_their_spans() {
+        let diagnostics = diagnose("---\ntitle x\n\n- [ ] a\n- b\n-\n\n> quoted\n>\n\n>\n");
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|(line, column, message)| (*line, *column, message.split(',').next().unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (1, 1, "`---` on the first line renders as a rule"),
+                (4, 1, "list mixes task items with plain items"),
+                (6, 1, "empty list item"),
+                (11, 1, "empty block quote"),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_footnote_labels_at_whitespace_and_brackets() {
+        let reference =
+            |input: &str| MarkdownParser::parse_footnote_reference(&MarkdownParser::lex(input));
+        assert_eq!(reference("[^note]: text"), Some(("note".to_string(), 4)));
+        assert_eq!(reference("[^a b] [^c]"), None);
+        assert_eq!(reference("[^a\n]"), None);
+        assert_eq!(reference("[^[^c]]"), None);
+        assert_eq!(reference("[^]"), None);
+        assert_eq!(reference("[^open"), None);
+    }
+
+    /// A page exercising every block and inline construct the parser knows.
+    const BENCH_PAGE: &str = r#"# Heading with *emphasis* and `code`
+
+A paragraph with **bold**, *italic*, `inline code`, a [link](https://example.com)
+and an image ![alt text](image.png), continued over a second line  
+after a hard break, with an autolink <https://example.com> at the end.
+
+> A quote with **bold** text
+> spread over two lines
+>
+> - and a list inside it
+
+- First item with [a link](page.html)
+- Second item
+  continued on another line
+
+  1. Nested numbered item
+  2. Another one
+
+| Column | Aligned | Right |
+|:-------|:-------:|------:|
+| `a`    | **b**   | c \| d |
+| 1      | 2       | 3      |
+
+```rust
+fn main() {
+    println!("Hello, world!");
+}
+```
+
+Setext heading
+--------------
+
+Some text with unicode — ünïcödé, 日本語 and emoji 🎉.
+"#;
+
+    /// Run with `cargo test --release -- --ignored --nocapture parse_benchmark`.
+    #[test]
+    #[ignore]
+    fn parse_benchmark() {
+        let input = BENCH_PAGE.repeat(500);
+        let iterations = 20;
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            let nodes = MarkdownParser::new(input.clone()).parse();
+            assert!(!nodes.is_empty());
+        }
+        let elapsed = start.elapsed();
+
+        let megabytes = (input.len() * iterations) as f64 / 1_000_000.0;
+        println!(
+            "parsed {:.1} MB in {:.2?} ({:.1} MB/s)",
+            megabytes,
+            elapsed,
+            megabytes / elapsed.as_secs_f64()
+        );
     }
 }
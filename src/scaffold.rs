@@ -0,0 +1,194 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    cli::{Config, Content},
+    error::Error,
+    parser::Extensions,
+};
+
+const INDEX_PAGE: &str = r#"+++
+title = "Home"
++++
+
+# Welcome
+
+This site was generated with `lssg`. Edit `content/index.md` to get started.
+"#;
+
+/// Creates a new site skeleton in `dir`, returning the files that were written.
+/// Fails without writing anything if any of the files already exist.
+pub fn init(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let title = dir
+        .canonicalize()
+        .unwrap_or_else(|_| dir.to_path_buf())
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "My site".to_string());
+
+    let config = Config {
+        title,
+        description: "A site built with lssg".to_string(),
+        theme: "frappe".to_string(),
+        content: Content {
+            latex_enabled: false,
+            extensions: Extensions::default(),
+        },
+    };
+    let config = toml::to_string(&config).map_err(|err| Error::Config {
+        path: dir.join("config.toml"),
+        message: format!("Failed to serialize config file: {}", err),
+    })?;
+
+    let files = [
+        (dir.join("config.toml"), config),
+        (dir.join("content").join("index.md"), INDEX_PAGE.to_string()),
+        (dir.join(".gitignore"), "dist\n".to_string()),
+    ];
+
+    if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+        return Err(Error::io(existing, refuse_overwrite()));
+    }
+
+    for (path, contents) in &files {
+        create_new_file(path, contents)?;
+    }
+
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Creates a new draft page at `path` under `content_dir`, pre-filled with
+/// front matter. A `.md` extension is added if `path` has none.
+pub fn new_page(content_dir: &Path, path: &Path) -> Result<PathBuf, Error> {
+    let mut target = content_dir.join(path);
+    if target.extension().is_none() {
+        target.set_extension("md");
+    }
+
+    let title = title_from_stem(&target);
+    let contents = format!(
+        "+++\ntitle = \"{}\"\ndate = {}\ndraft = true\n+++\n\n# {}\n",
+        title.replace('\\', "\\\\").replace('"', "\\\""),
+        today(),
+        title
+    );
+
+    create_new_file(&target, &contents)?;
+
+    Ok(target)
+}
+
+fn create_new_file(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+    }
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => Error::io(path, refuse_overwrite()),
+            _ => Error::io(path, err),
+        })
+}
+
+fn refuse_overwrite() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "refusing to overwrite existing file",
+    )
+}
+
+/// Turns a file name such as `my-first-post.md` into `My first post`.
+fn title_from_stem(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace(['-', '_'], " "))
+        .unwrap_or_default();
+
+    let mut chars = stem.trim().chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => "Untitled".to_string(),
+    }
+}
+
+/// Today's UTC date as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / 86_400)
+        .unwrap_or_default() as i64;
+
+    civil_date(days)
+}
+
+/// Converts days since the Unix epoch to a `YYYY-MM-DD` date, see
+/// https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_date(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter::split_front_matter;
+
+    #[test]
+    fn converts_days_to_civil_dates() {
+        for (days, date) in [
+            (0, "1970-01-01"),
+            (-1, "1969-12-31"),
+            (10_956, "1999-12-31"),
+            (10_957, "2000-01-01"),
+            (19_782, "2024-02-29"),
+            (19_783, "2024-03-01"),
+            (-25_508, "1900-03-01"),
+        ] {
+            assert_eq!(civil_date(days), date, "{} days", days);
+        }
+    }
+
+    #[test]
+    fn titles_pages_from_their_file_names() {
+        for (path, title) in [
+            ("my-first-post.md", "My first post"),
+            ("notes/snake_case_name.md", "Snake case name"),
+            ("élan.md", "Élan"),
+            ("-.md", "Untitled"),
+        ] {
+            assert_eq!(title_from_stem(Path::new(path)), title);
+        }
+    }
+
+    #[test]
+    fn escapes_titles_in_new_page_front_matter() {
+        let content_dir =
+            std::env::temp_dir().join(format!("lssg-scaffold-{}", std::process::id()));
+        let page = new_page(&content_dir, Path::new(r#"say-"hi"-c:\dir"#)).unwrap();
+        let contents = fs::read_to_string(&page).unwrap();
+        let _ = fs::remove_dir_all(&content_dir);
+
+        let (front_matter, body) = split_front_matter(&contents).unwrap();
+        assert_eq!(page.extension().unwrap(), "md");
+        assert_eq!(front_matter.title.as_deref(), Some(r#"Say "hi" c:\dir"#));
+        assert!(front_matter.draft);
+        assert_eq!(body, "\n# Say \"hi\" c:\\dir\n");
+    }
+}
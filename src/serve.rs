@@ -0,0 +1,204 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use owo_colors::OwoColorize;
+
+use crate::{
+    build::BuildOptions,
+    cli::Config,
+    dist,
+    error::Error,
+    watch::{Rebuilder, SiteWatcher},
+};
+
+/// Browsers listening on the live reload endpoint.
+type Clients = Arc<Mutex<Vec<TcpStream>>>;
+
+/// Builds the site into a temporary directory, serves it on `port` and
+/// rebuilds pages as their sources change, telling open browsers to reload
+/// afterwards. Pages carry the live reload client, so they are kept out of
+/// `dist/` where they could be deployed after the server stops.
+pub fn serve(current_dir: &Path, config: Config, port: u16, verbose: bool) -> Result<(), Error> {
+    let options = BuildOptions {
+        verbose,
+        live_reload: true,
+    };
+
+    let serve_dir = std::env::temp_dir().join(format!("lssg-serve-{}", std::process::id()));
+    let watcher = SiteWatcher::new(current_dir, false)?;
+    let mut rebuilder = Rebuilder::new(current_dir, &serve_dir, config, options);
+    rebuilder.build_all();
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|err| Error::Server(format!("Failed to bind to port {}: {}", port, err)))?;
+    let clients = Clients::default();
+
+    let dirs = Arc::new(ServeDirs {
+        pages: serve_dir.clone(),
+        dist: dist::dist_dir(current_dir),
+    });
+    let server_clients = clients.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let dirs = dirs.clone();
+            let clients = server_clients.clone();
+            thread::spawn(move || handle_connection(stream, &dirs, &clients));
+        }
+    });
+
+    println!(" {} at http://localhost:{}", "Serving".green().bold(), port);
+
+    while let Some(changes) = watcher.next_changes() {
+        rebuilder.apply(&changes);
+        reload_clients(&clients);
+    }
+
+    let _ = fs::remove_dir_all(&serve_dir);
+    Ok(())
+}
+
+/// Where requests are answered from.
+struct ServeDirs {
+    /// The pages built by `lssg serve`.
+    pages: PathBuf,
+    /// `dist/`, for files placed there by hand such as images.
+    dist: PathBuf,
+}
+
+impl ServeDirs {
+    /// Finds the file for a request path, preferring the served pages. Outputs
+    /// of `lssg build` are never served from `dist/`, since they may be stale.
+    fn resolve(&self, url_path: &str) -> Option<PathBuf> {
+        resolve_path(&self.pages, url_path).or_else(|| {
+            resolve_path(&self.dist, url_path).filter(|file| {
+                let outputs = dist::read_manifest(&self.dist);
+                file.strip_prefix(&self.dist)
+                    .is_ok_and(|output| !outputs.contains(output))
+            })
+        })
+    }
+}
+
+fn reload_clients(clients: &Clients) {
+    let mut clients = clients.lock().unwrap();
+    clients.retain_mut(|stream| stream.write_all(b"data: reload\n\n").is_ok());
+}
+
+fn handle_connection(mut stream: TcpStream, dirs: &ServeDirs, clients: &Clients) {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+
+    // Skip the request headers, nothing in them changes the response
+    let mut header = String::new();
+    while reader.read_line(&mut header).is_ok_and(|n| n > 0) && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return;
+    };
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+
+    if path == dist::LIVE_RELOAD_PATH {
+        let headers =
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n";
+        if stream.write_all(headers.as_bytes()).is_ok() {
+            clients.lock().unwrap().push(stream);
+        }
+        return;
+    }
+
+    let file = dirs
+        .resolve(path)
+        .and_then(|file| Some((content_type(&file), fs::read(&file).ok()?)));
+    let (status, content_type, body) = match file {
+        Some((content_type, body)) => ("200 OK", content_type, body),
+        None => ("404 Not Found", "text/plain", b"404 Not Found".to_vec()),
+    };
+
+    let headers = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+
+    let _ = stream.write_all(headers.as_bytes());
+    if method != "HEAD" {
+        let _ = stream.write_all(&body);
+    }
+}
+
+/// Maps a request path onto a file in `dir`, trying `index.html` for
+/// directories and adding `.html` for extensionless paths.
+fn resolve_path(dir: &Path, url_path: &str) -> Option<PathBuf> {
+    let relative = PathBuf::from(percent_decode(url_path.trim_start_matches('/')));
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = dir.join(relative);
+    if path.is_dir() {
+        Some(path.join("index.html")).filter(|index| index.is_file())
+    } else if path.is_file() {
+        Some(path)
+    } else {
+        Some(path.with_extension("html")).filter(|html| html.is_file())
+    }
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,70 @@
+/// A location in a markdown source, with a 1-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    /// Byte offset from the start of the source.
+    pub offset: usize,
+    pub line: usize,
+    /// Column counted in characters.
+    pub column: usize,
+}
+
+/// The region of the source a node or span was parsed from, `end` being
+/// exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SourceSpan {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Position {
+    /// The start of a file.
+    pub const START: Position = Position {
+        offset: 0,
+        line: 1,
+        column: 1,
+    };
+}
+
+/// Finds the line and column of byte offsets in a source, which itself starts
+/// at `start` in its file.
+#[derive(Debug)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    start: Position,
+}
+
+impl LineIndex {
+    pub fn new(source: &str, start: Position) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        LineIndex { line_starts, start }
+    }
+
+    pub fn position(&self, source: &str, offset: usize) -> Position {
+        let offset = offset.min(source.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        let column = source
+            .get(line_start..offset)
+            .map_or(offset - line_start, |text| text.chars().count())
+            + 1;
+
+        Position {
+            offset: self.start.offset + offset,
+            line: self.start.line + line - 1,
+            column: match line {
+                1 => self.start.column + column - 1,
+                _ => column,
+            },
+        }
+    }
+
+    pub fn span(&self, source: &str, start: usize, end: usize) -> SourceSpan {
+        SourceSpan {
+            start: self.position(source, start),
+            end: self.position(source, end.max(start)),
+        }
+    }
+}
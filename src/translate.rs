@@ -1,94 +1,606 @@
-use crate::parser::{self, MarkdownNodes, MarkdownParagraph, MarkdownSpan, MarkdownVariant};
+use std::collections::HashMap;
+
+use crate::parser::{
+    self, MarkdownAlignment, MarkdownFootnote, MarkdownListItem, MarkdownNodes, MarkdownParagraph,
+    MarkdownSpan, MarkdownVariant,
+};
+
+/// Escapes text for use in HTML element content or a quoted attribute value.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
 
 pub struct Translator<'a> {
     nodes: &'a Vec<MarkdownNodes>,
+    /// Footnote definitions by label, the first one winning when a label is
+    /// defined more than once.
+    footnotes: HashMap<&'a str, &'a MarkdownFootnote>,
+    /// Labels of the footnotes referenced so far in the order they were
+    /// first referenced, which gives their numbers, along with how many times
+    /// each was referenced.
+    referenced: Vec<(&'a str, usize)>,
 }
 
 impl<'a> Translator<'a> {
     pub fn new(nodes: &'a Vec<MarkdownNodes>) -> Self {
-        Translator { nodes }
+        let mut footnotes = HashMap::new();
+        Self::collect_footnotes(nodes, &mut footnotes);
+
+        Translator {
+            nodes,
+            footnotes,
+            referenced: Vec::new(),
+        }
+    }
+
+    fn collect_footnotes(
+        nodes: &'a [MarkdownNodes],
+        footnotes: &mut HashMap<&'a str, &'a MarkdownFootnote>,
+    ) {
+        for node in nodes {
+            match node {
+                MarkdownNodes::Footnote(f) => {
+                    footnotes.entry(f.label.as_str()).or_insert(f);
+                }
+                MarkdownNodes::BlockQuote(q) => Self::collect_footnotes(&q.children, footnotes),
+                MarkdownNodes::List(l) => {
+                    for item in &l.items {
+                        Self::collect_footnotes(&item.children, footnotes);
+                    }
+                }
+                _ => {}
+            }
+        }
     }
 
-    fn format_span(span: &MarkdownSpan) -> String {
-        match span.variant {
-            MarkdownVariant::Bold => format!("<strong>{}</strong>", span.content),
-            MarkdownVariant::Italic => format!("<em>{}</em>", span.content),
-            MarkdownVariant::InlineCode => format!("<code>{}</code>", span.content),
-            MarkdownVariant::Normal => span.content.clone(),
+    /// Records a reference to a footnote, returning its number and how many
+    /// times it has been referenced including this one. Undefined footnotes
+    /// have no number.
+    fn reference_footnote(&mut self, label: &'a str) -> Option<(usize, usize)> {
+        if !self.footnotes.contains_key(label) {
+            return None;
         }
+
+        let index = match self.referenced.iter().position(|(l, _)| *l == label) {
+            Some(index) => index,
+            None => {
+                self.referenced.push((label, 0));
+                self.referenced.len() - 1
+            }
+        };
+        self.referenced[index].1 += 1;
+
+        Some((index + 1, self.referenced[index].1))
     }
 
-    fn format_paragraph(paragraph: &MarkdownParagraph) -> String {
+    /// The ` title="..."` attribute of a link or image, if it has a title.
+    fn title_attribute(title: &Option<String>) -> String {
+        title
+            .as_ref()
+            .map(|title| format!(" title=\"{}\"", escape_html(title)))
+            .unwrap_or_default()
+    }
+
+    /// The id of the `occurrence`th reference to footnote `number`, which its
+    /// back-link points to.
+    fn reference_id(number: usize, occurrence: usize) -> String {
+        match occurrence {
+            1 => format!("fnref-{}", number),
+            _ => format!("fnref-{}-{}", number, occurrence),
+        }
+    }
+
+    fn format_span(&mut self, span: &'a MarkdownSpan) -> String {
+        let content = escape_html(&span.content);
+        let children = self.format_spans(&span.children);
+        match &span.variant {
+            MarkdownVariant::Bold => format!("<strong>{}</strong>", children),
+            MarkdownVariant::Italic => format!("<em>{}</em>", children),
+            MarkdownVariant::InlineCode => format!("<code>{}</code>", content),
+            MarkdownVariant::Normal => content,
+            MarkdownVariant::Link { url, title } => format!(
+                "<a href=\"{}\"{}>{}</a>",
+                escape_html(url),
+                Self::title_attribute(title),
+                children
+            ),
+            MarkdownVariant::Image { url, title } => format!(
+                "<img src=\"{}\" alt=\"{}\"{} />",
+                escape_html(url),
+                Self::plain_text(&span.children),
+                Self::title_attribute(title)
+            ),
+            MarkdownVariant::AutoLink => {
+                let href = match span.content.contains(':') {
+                    true => content.clone(),
+                    false => format!("mailto:{}", content),
+                };
+                format!("<a href=\"{}\">{}</a>", href, content)
+            }
+            MarkdownVariant::LineBreak => "<br />".to_string(),
+            MarkdownVariant::Strikethrough => format!("<del>{}</del>", children),
+            MarkdownVariant::Highlight => format!("<mark>{}</mark>", children),
+            MarkdownVariant::Superscript => format!("<sup>{}</sup>", children),
+            MarkdownVariant::Subscript => format!("<sub>{}</sub>", children),
+            MarkdownVariant::FootnoteReference(label) => match self.reference_footnote(label) {
+                Some((number, occurrence)) => format!(
+                    "<sup id=\"{}\"><a href=\"#fn-{}\">{}</a></sup>",
+                    Self::reference_id(number, occurrence),
+                    number,
+                    number
+                ),
+                // References to undefined footnotes are left as they were written
+                None => escape_html(&format!("[^{}]", label)),
+            },
+        }
+    }
+
+    fn format_spans(&mut self, spans: &'a [MarkdownSpan]) -> String {
         let mut html = String::new();
-        for span in &paragraph.spans {
-            html.push_str(&Self::format_span(span));
+        for span in spans {
+            html.push_str(&self.format_span(span));
         }
         html
     }
 
-    pub fn translate(&self, class: &str) -> String {
-        let mut output = String::new();
-        for node in self.nodes {
-            match node {
-                MarkdownNodes::Paragraph(p) => {
-                    output.push_str(&format!(
-                        "<p class=\"{}\">{}</p>",
+    /// Flattens spans to their escaped text content, for places where markup
+    /// is not allowed (such as an image's `alt` attribute).
+    fn plain_text(spans: &[MarkdownSpan]) -> String {
+        spans
+            .iter()
+            .map(|span| escape_html(&span.content) + &Self::plain_text(&span.children))
+            .collect()
+    }
+
+    fn format_paragraph(&mut self, paragraph: &'a MarkdownParagraph) -> String {
+        self.format_spans(&paragraph.spans)
+    }
+
+    fn format_table_cell(
+        &mut self,
+        tag: &str,
+        class: &str,
+        alignment: MarkdownAlignment,
+        spans: &'a [MarkdownSpan],
+    ) -> String {
+        let style = match alignment {
+            MarkdownAlignment::None => "",
+            MarkdownAlignment::Left => " style=\"text-align:left\"",
+            MarkdownAlignment::Center => " style=\"text-align:center\"",
+            MarkdownAlignment::Right => " style=\"text-align:right\"",
+        };
+        let content = self.format_spans(spans);
+        format!(
+            "<{} class=\"{}\"{}>{}</{}>",
+            tag, class, style, content, tag
+        )
+    }
+
+    fn format_list_item(&mut self, item: &'a MarkdownListItem, loose: bool, class: &str) -> String {
+        let mut checkbox = match item.checked {
+            Some(checked) => format!(
+                "<input type=\"checkbox\" class=\"{}\" disabled{} /> ",
+                class,
+                if checked { " checked" } else { "" }
+            ),
+            None => String::new(),
+        };
+
+        // Tight items inline their paragraphs, loose ones wrap them in <p>
+        // with the checkbox inside the first one
+        let mut content = String::new();
+        for (i, child) in item.children.iter().enumerate() {
+            match child {
+                MarkdownNodes::Paragraph(p) if loose => {
+                    let checkbox = match i {
+                        0 => std::mem::take(&mut checkbox),
+                        _ => String::new(),
+                    };
+                    content.push_str(&format!(
+                        "<p class=\"{}\">{}{}</p>",
                         class,
-                        Self::format_paragraph(p)
-                    ));
-                }
-                MarkdownNodes::Heading(h) => {
-                    output.push_str(&format!(
-                        "<h{} class=\"{}\">{}</h{}>",
-                        h.level, class, h.content, h.level
+                        checkbox,
+                        self.format_paragraph(p)
                     ));
                 }
-                MarkdownNodes::Link(l) => {
-                    let node = match l.is_image {
-                        true => format!(
-                            "<img src=\"{}\" alt=\"{}\" class=\"{}\" />",
-                            l.url, l.alt, class
-                        ),
-                        false => format!("<a href=\"{}\" class=\"{}\">{}</a>", l.url, class, l.alt),
-                    };
-                    output.push_str(&node);
-                }
-                MarkdownNodes::List(l) => {
-                    let node = match l.ty {
-                        parser::MarkdownListType::Numbered => "ol",
-                        parser::MarkdownListType::Bulleted => "ul",
-                    };
+                MarkdownNodes::Paragraph(p) => content.push_str(&self.format_paragraph(p)),
+                _ => content.push_str(&self.translate_node(child, class)),
+            }
+        }
+
+        checkbox + &content
+    }
+
+    fn translate_node(&mut self, node: &'a MarkdownNodes, class: &str) -> String {
+        match node {
+            MarkdownNodes::Paragraph(p) => {
+                format!("<p class=\"{}\">{}</p>", class, self.format_paragraph(p))
+            }
+            MarkdownNodes::Heading(h) => format!(
+                "<h{} class=\"{}\">{}</h{}>",
+                h.level,
+                class,
+                self.format_spans(&h.content),
+                h.level
+            ),
+            MarkdownNodes::Link(l) => match l.is_image {
+                true => format!(
+                    "<img src=\"{}\" alt=\"{}\"{} class=\"{}\" />",
+                    escape_html(&l.url),
+                    Self::plain_text(&l.alt),
+                    Self::title_attribute(&l.title),
+                    class
+                ),
+                false => format!(
+                    "<a href=\"{}\"{} class=\"{}\">{}</a>",
+                    escape_html(&l.url),
+                    Self::title_attribute(&l.title),
+                    class,
+                    self.format_spans(&l.alt)
+                ),
+            },
+            MarkdownNodes::List(l) => {
+                let node = match l.ty {
+                    parser::MarkdownListType::Numbered => "ol",
+                    parser::MarkdownListType::Bulleted => "ul",
+                };
+
+                let items = l
+                    .items
+                    .iter()
+                    .map(|i| {
+                        format!(
+                            "<li class=\"{}\">{}</li>",
+                            class,
+                            self.format_list_item(i, l.loose, class)
+                        )
+                    })
+                    .collect::<String>();
 
-                    let items = l
-                        .items
+                // Numbered lists count from 1 unless told otherwise
+                let start = match l.start {
+                    Some(start) if start != 1 => format!(" start=\"{}\"", start),
+                    _ => String::new(),
+                };
+
+                format!(
+                    "<{}{} class=\"{}\">{}</{}>",
+                    node,
+                    start,
+                    class,
+                    items.as_str(),
+                    node
+                )
+            }
+            MarkdownNodes::CodeBlock(c) => format!(
+                "<pre><code class=\"language-{} {}\">{}</code></pre>",
+                escape_html(&c.language),
+                class,
+                escape_html(&c.content)
+            ),
+            MarkdownNodes::BlockQuote(q) => {
+                let inner = q
+                    .children
+                    .iter()
+                    .map(|child| self.translate_node(child, class))
+                    .collect::<String>();
+                format!("<blockquote class=\"{}\">{}</blockquote>", class, inner)
+            }
+            MarkdownNodes::Table(t) => {
+                let mut row = |tag: &str, cells: &'a Vec<Vec<MarkdownSpan>>| {
+                    let cells = cells
                         .iter()
-                        .map(|i| format!("<li class=\"{}\">{}</li>", class, i))
+                        .zip(&t.alignments)
+                        .map(|(cell, alignment)| {
+                            self.format_table_cell(tag, class, *alignment, cell)
+                        })
                         .collect::<String>();
+                    format!("<tr class=\"{}\">{}</tr>", class, cells)
+                };
 
-                    let out = format!(
-                        "<{} class=\"{}\">{}</{}>",
-                        node,
-                        class,
-                        items.as_str(),
-                        node
-                    );
-
-                    output.push_str(&out);
-                }
-                MarkdownNodes::CodeBlock(c) => {
-                    let node = format!(
-                        "<pre><code class=\"language-{} {}\">{}</code></pre>",
-                        c.language, class, c.content
-                    );
-                    output.push_str(&node);
-                }
-                MarkdownNodes::HorizontalRule => {
-                    let node = format!("<hr class=\"{}\" />", class);
-                    output.push_str(&node);
+                let mut node = format!(
+                    "<table class=\"{}\"><thead>{}</thead>",
+                    class,
+                    row("th", &t.header)
+                );
+                if !t.rows.is_empty() {
+                    let body = t.rows.iter().map(|r| row("td", r)).collect::<String>();
+                    node.push_str(&format!("<tbody>{}</tbody>", body));
                 }
-                _ => {}
+                node.push_str("</table>");
+                node
             }
+            MarkdownNodes::HorizontalRule(_) => format!("<hr class=\"{}\" />", class),
+            // Footnotes are rendered together at the end of the page
+            MarkdownNodes::Footnote(_) => String::new(),
+        }
+    }
+
+    /// Renders the referenced footnotes in order, each linking back to its
+    /// references. Footnotes referenced from other footnotes are added to the
+    /// end as they are found.
+    fn format_footnotes(&mut self, class: &str) -> String {
+        let mut items = String::new();
+        let mut index = 0;
+
+        while let Some(&(label, _)) = self.referenced.get(index) {
+            index += 1;
+            let footnote = self.footnotes[label];
+            let content = footnote
+                .children
+                .iter()
+                .map(|child| self.translate_node(child, class))
+                .collect::<String>();
+
+            let back_links = (1..=self.referenced[index - 1].1)
+                .map(|occurrence| {
+                    format!(
+                        "<a href=\"#{}\" class=\"{}\">↩</a>",
+                        Self::reference_id(index, occurrence),
+                        class
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            // Keep the links on the last line of the footnote
+            let content = match content.strip_suffix("</p>") {
+                Some(content) => format!("{} {}</p>", content, back_links),
+                None => content + &back_links,
+            };
+
+            items.push_str(&format!(
+                "<li id=\"fn-{}\" class=\"{}\">{}</li>",
+                index, class, content
+            ));
+        }
+
+        match items.is_empty() {
+            true => items,
+            false => format!(
+                "<section id=\"footnotes\" class=\"{}\"><hr class=\"{}\" /><ol class=\"{}\">{}</ol></section>",
+                class, class, class, items
+            ),
+        }
+    }
+
+    pub fn translate(&mut self, class: &str) -> String {
+        let class = escape_html(class);
+        let mut output = String::new();
+        for node in self.nodes {
+            output.push_str(&self.translate_node(node, &class));
         }
+        output.push_str(&self.format_footnotes(&class));
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Extensions, MarkdownParser};
+
+    fn render(input: &str) -> String {
+        let nodes = MarkdownParser::new(input.to_string()).parse();
+        Translator::new(&nodes).translate("")
+    }
+
+    #[test]
+    fn escapes_html_in_code_blocks() {
+        let html = render("```rust\nlet v: Vec<String> = vec![];\nif a < b && c > d {}\n```");
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-rust \">let v: Vec&lt;String&gt; = vec![];\n\
+             if a &lt; b &amp;&amp; c &gt; d {}\n</code></pre>"
+        );
+    }
+
+    #[test]
+    fn escapes_script_tags_in_code_blocks() {
+        let html = render("```html\n<script>alert(\"hi\")</script>\n```");
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(&quot;hi&quot;)&lt;/script&gt;"));
+    }
+
+    #[test]
+    fn escapes_code_block_language() {
+        let html = render("```\"><script>\nx\n```");
+        assert!(html.starts_with("<pre><code class=\"language-&quot;&gt;&lt;script&gt; \">"));
+    }
+
+    #[test]
+    fn escapes_text_content() {
+        assert_eq!(
+            render("<script>alert(1)</script> & `a < b`"),
+            "<p class=\"\">&lt;script&gt;alert(1)&lt;/script&gt; &amp; <code>a &lt; b</code></p>"
+        );
+        assert_eq!(
+            render("## Vec<T> & friends"),
+            "<h2 class=\"\">Vec&lt;T&gt; &amp; friends</h2>"
+        );
+    }
+
+    #[test]
+    fn escapes_attribute_values() {
+        assert_eq!(
+            render("![a \"quote\"](https://example.com/?a=1&b=\"2\")"),
+            "<img src=\"https://example.com/?a=1&amp;b=&quot;2&quot;\" alt=\"a &quot;quote&quot;\" class=\"\" />"
+        );
+        assert_eq!(
+            Translator::new(&MarkdownParser::new("---".to_string()).parse()).translate("\"x"),
+            "<hr class=\"&quot;x\" />"
+        );
+    }
+
+    #[test]
+    fn renders_extension_spans_unless_disabled() {
+        let input = "~~a~~ ==b== c^d^ e~f~";
+        assert_eq!(
+            render(input),
+            "<p class=\"\"><del>a</del> <mark>b</mark> c<sup>d</sup> e<sub>f</sub></p>"
+        );
+
+        let extensions = Extensions {
+            strikethrough: false,
+            highlight: false,
+            superscript: false,
+            subscript: false,
+        };
+        let nodes = MarkdownParser::new(input.to_string())
+            .with_extensions(extensions)
+            .parse();
+        assert_eq!(
+            Translator::new(&nodes).translate(""),
+            format!("<p class=\"\">{}</p>", input)
+        );
+    }
+
+    #[test]
+    fn renders_task_list_checkboxes() {
+        assert_eq!(
+            render("- [ ] todo\n- [x] done\n- [x]text"),
+            "<ul class=\"\"><li class=\"\"><input type=\"checkbox\" class=\"\" disabled /> todo</li>\
+             <li class=\"\"><input type=\"checkbox\" class=\"\" disabled checked /> done</li>\
+             <li class=\"\">[x]text</li></ul>"
+        );
+    }
+
+    #[test]
+    fn numbers_footnotes_by_first_reference() {
+        let html = render("a[^b] c[^a] d[^b] e[^missing]\n\n[^a]: A\n[^b]: B\n[^unused]: C");
+        assert_eq!(
+            html,
+            "<p class=\"\">a<sup id=\"fnref-1\"><a href=\"#fn-1\">1</a></sup> \
+             c<sup id=\"fnref-2\"><a href=\"#fn-2\">2</a></sup> \
+             d<sup id=\"fnref-1-2\"><a href=\"#fn-1\">1</a></sup> e[^missing]</p>\
+             <section id=\"footnotes\" class=\"\"><hr class=\"\" /><ol class=\"\">\
+             <li id=\"fn-1\" class=\"\"><p class=\"\">B \
+             <a href=\"#fnref-1\" class=\"\">↩</a> <a href=\"#fnref-1-2\" class=\"\">↩</a></p></li>\
+             <li id=\"fn-2\" class=\"\"><p class=\"\">A <a href=\"#fnref-2\" class=\"\">↩</a></p></li>\
+             </ol></section>"
+        );
+    }
+
+    #[test]
+    fn resolves_reference_links_against_later_definitions() {
+        let input = "[a][Ref], [ref][] and ![ref] [missing]\n\n\
+                     [REF]: /target 'Title'\n[ref]: /ignored";
+        assert_eq!(
+            render(input),
+            "<p class=\"\"><a href=\"/target\" title=\"Title\">a</a>, \
+             <a href=\"/target\" title=\"Title\">ref</a> and \
+             <img src=\"/target\" alt=\"ref\" title=\"Title\" /> [missing]</p>"
+        );
+    }
+
+    #[test]
+    fn continues_quoted_paragraphs_lazily() {
+        assert_eq!(
+            render("> quoted text that\nwraps lazily\n\n> # heading\nafter"),
+            "<blockquote class=\"\"><p class=\"\">quoted text that\nwraps lazily</p></blockquote>\
+             <blockquote class=\"\"><h1 class=\"\">heading</h1></blockquote><p class=\"\">after</p>"
+        );
+    }
+
+    #[test]
+    fn continues_list_items_lazily() {
+        assert_eq!(
+            render("- a long item that\nwraps onto a second line\n- next"),
+            "<ul class=\"\"><li class=\"\">a long item that\nwraps onto a second line</li>\
+             <li class=\"\">next</li></ul>"
+        );
+    }
+
+    #[test]
+    fn wraps_paragraphs_of_loose_list_items() {
+        assert_eq!(
+            render("- item one\n\n  second paragraph of item one\n- [x] item two\n\n  more"),
+            "<ul class=\"\"><li class=\"\"><p class=\"\">item one</p>\
+             <p class=\"\">second paragraph of item one</p></li>\
+             <li class=\"\"><p class=\"\"><input type=\"checkbox\" class=\"\" disabled checked /> \
+             item two</p><p class=\"\">more</p></li></ul>"
+        );
+    }
+
+    #[test]
+    fn renders_table_alignments() {
+        assert_eq!(
+            render("| a | b | c | d |\n|:--|:-:|--:|---|\n| 1 | 2 | 3 | 4 |"),
+            "<table class=\"\"><thead><tr class=\"\"><th class=\"\" style=\"text-align:left\">a</th>\
+             <th class=\"\" style=\"text-align:center\">b</th>\
+             <th class=\"\" style=\"text-align:right\">c</th><th class=\"\">d</th></tr></thead>\
+             <tbody><tr class=\"\"><td class=\"\" style=\"text-align:left\">1</td>\
+             <td class=\"\" style=\"text-align:center\">2</td>\
+             <td class=\"\" style=\"text-align:right\">3</td><td class=\"\">4</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn keeps_escaped_pipes_in_table_cells() {
+        assert_eq!(
+            render("| a | b |\n|---|---|\n| x \\| y | `p \\| q` |"),
+            "<table class=\"\"><thead><tr class=\"\"><th class=\"\">a</th><th class=\"\">b</th></tr>\
+             </thead><tbody><tr class=\"\"><td class=\"\">x | y</td>\
+             <td class=\"\"><code>p | q</code></td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn pads_and_truncates_table_rows_to_the_header() {
+        assert_eq!(
+            render("| a | b |\n|---|---|\n| 1 |\n| 1 | 2 | 3 |"),
+            "<table class=\"\"><thead><tr class=\"\"><th class=\"\">a</th><th class=\"\">b</th></tr>\
+             </thead><tbody><tr class=\"\"><td class=\"\">1</td><td class=\"\"></td></tr>\
+             <tr class=\"\"><td class=\"\">1</td><td class=\"\">2</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn renders_tables_without_body_rows() {
+        assert_eq!(
+            render("| a | b |\n|---|---|"),
+            "<table class=\"\"><thead><tr class=\"\"><th class=\"\">a</th><th class=\"\">b</th></tr>\
+             </thead></table>"
+        );
+    }
+
+    #[test]
+    fn needs_a_delimiter_cell_per_header_cell() {
+        assert_eq!(
+            render("| a | b |\n|---|"),
+            "<p class=\"\">| a | b |\n|---|</p>"
+        );
+    }
+
+    #[test]
+    fn starts_numbered_lists_at_their_first_number() {
+        assert_eq!(
+            render("3. foo\n4. bar"),
+            "<ol start=\"3\" class=\"\"><li class=\"\">foo</li><li class=\"\">bar</li></ol>"
+        );
+        assert_eq!(
+            render("1. foo"),
+            "<ol class=\"\"><li class=\"\">foo</li></ol>"
+        );
+    }
+
+    #[test]
+    fn loosens_lists_with_blank_lines_between_items() {
+        assert_eq!(
+            render("- a\n\n- b\n- c"),
+            "<ul class=\"\"><li class=\"\"><p class=\"\">a</p></li><li class=\"\"><p class=\"\">b</p></li>\
+             <li class=\"\"><p class=\"\">c</p></li></ul>"
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use notify::{Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use owo_colors::OwoColorize;
+
+use crate::{
+    build::{self, BuildOptions, PageOutcome},
+    cli::{self, Config, find_target_files},
+    dist,
+    error::Error,
+};
+
+/// How long the content tree has to be quiet before a burst of events is
+/// handed on, so saving many files at once only triggers one rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A debounced batch of changes to the site sources.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// `config.toml` was changed, so every page needs rebuilding.
+    pub config: bool,
+    /// A directory under `content/` was changed, so the tree needs rescanning.
+    pub rescan: bool,
+    /// Markdown files that were created, modified or removed.
+    pub pages: BTreeSet<PathBuf>,
+}
+
+/// Watches `content/` and `config.toml`, using native filesystem
+/// notifications where available and falling back to polling.
+pub struct SiteWatcher {
+    _watcher: Box<dyn Watcher>,
+    events: Receiver<notify::Result<Event>>,
+    content_dir: PathBuf,
+    config_path: PathBuf,
+}
+
+impl SiteWatcher {
+    pub fn new(current_dir: &Path, force_poll: bool) -> Result<Self, Error> {
+        let content_dir = current_dir.join("content");
+        let config_path = cli::get_config_file_path(current_dir);
+
+        let (sender, events) = mpsc::channel();
+        let native = match force_poll {
+            true => None,
+            false => RecommendedWatcher::new(sender.clone(), notify::Config::default())
+                .and_then(|watcher| Self::watch_paths(watcher, current_dir, &content_dir))
+                .ok(),
+        };
+
+        let watcher = match native {
+            Some(watcher) => watcher,
+            None => {
+                let config = notify::Config::default().with_poll_interval(POLL_INTERVAL);
+                PollWatcher::new(sender, config)
+                    .and_then(|watcher| Self::watch_paths(watcher, current_dir, &content_dir))
+                    .map_err(|err| {
+                        Error::Server(format!(
+                            "Failed to watch `{}`: {}",
+                            content_dir.display(),
+                            err
+                        ))
+                    })?
+            }
+        };
+
+        Ok(SiteWatcher {
+            _watcher: watcher,
+            events,
+            content_dir,
+            config_path,
+        })
+    }
+
+    fn watch_paths<W: Watcher + 'static>(
+        mut watcher: W,
+        current_dir: &Path,
+        content_dir: &Path,
+    ) -> notify::Result<Box<dyn Watcher>> {
+        watcher.watch(content_dir, RecursiveMode::Recursive)?;
+        // Watch the directory rather than the file, since editors often save by
+        // replacing `config.toml` instead of writing to it
+        watcher.watch(current_dir, RecursiveMode::NonRecursive)?;
+        Ok(Box::new(watcher))
+    }
+
+    /// Blocks until something changes, then collects events until the tree has
+    /// been quiet for [`DEBOUNCE`]. Returns `None` once the watcher has stopped.
+    pub fn next_changes(&self) -> Option<Changes> {
+        let mut changes = Changes::default();
+        let mut event = self.events.recv().ok()?;
+
+        loop {
+            match event {
+                Ok(event) => self.record(&mut changes, event),
+                Err(err) => eprintln!("{}: {}", "Error".bold().red(), err),
+            }
+
+            match self.events.recv_timeout(DEBOUNCE) {
+                Ok(next) => event = next,
+                Err(_) if changes.config || changes.rescan || !changes.pages.is_empty() => {
+                    return Some(changes);
+                }
+                Err(_) => event = self.events.recv().ok()?,
+            }
+        }
+    }
+
+    fn record(&self, changes: &mut Changes, event: Event) {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+
+        for path in event.paths {
+            if path == self.config_path {
+                changes.config = true;
+            } else if !path.starts_with(&self.content_dir) {
+                continue;
+            } else if path.extension().is_some_and(|ext| ext == "md") {
+                changes.pages.insert(path);
+            } else if path.is_dir() || !path.exists() {
+                changes.rescan = true;
+            }
+        }
+    }
+}
+
+/// Keeps an output directory in sync with the sources as changes come in, only
+/// re-running the pipeline for pages that changed.
+pub struct Rebuilder {
+    current_dir: PathBuf,
+    dist_dir: PathBuf,
+    config: Config,
+    options: BuildOptions,
+    /// Sources that were part of the last build, used to find removed pages.
+    sources: BTreeSet<PathBuf>,
+    /// Sources skipped because another source is written to the same output.
+    colliding: BTreeSet<PathBuf>,
+}
+
+impl Rebuilder {
+    pub fn new(current_dir: &Path, dist_dir: &Path, config: Config, options: BuildOptions) -> Self {
+        Rebuilder {
+            current_dir: current_dir.to_path_buf(),
+            dist_dir: dist_dir.to_path_buf(),
+            config,
+            options,
+            sources: BTreeSet::new(),
+            colliding: BTreeSet::new(),
+        }
+    }
+
+    /// Builds every page, removing the output of any source that has gone.
+    /// Sources that would overwrite each other's output are skipped.
+    pub fn build_all(&mut self) {
+        let content_dir = self.current_dir.join("content");
+        let targets = match find_target_files(content_dir.clone(), "md") {
+            Ok(targets) => targets,
+            Err(err) => {
+                eprintln!("{}: {}", "Error".bold().red(), err);
+                return;
+            }
+        };
+
+        let sources: BTreeSet<PathBuf> = targets.into_iter().collect();
+        for removed in self.sources.difference(&sources) {
+            self.remove_output(removed);
+        }
+        self.sources = sources;
+        self.colliding = self.find_collisions();
+
+        let mut outputs = Vec::new();
+        for target in self.sources.difference(&self.colliding) {
+            match build::build_page(
+                &self.current_dir,
+                &self.dist_dir,
+                target,
+                &self.config,
+                self.options,
+            ) {
+                Ok(PageOutcome::Written(output, _, warnings)) => {
+                    for warning in &warnings {
+                        println!(
+                            "{}",
+                            build::format_warning(&self.current_dir, target, warning)
+                        );
+                    }
+                    outputs.push(output);
+                }
+                Ok(PageOutcome::Draft) => self.remove_output(target),
+                Err(err) => eprintln!("{}: {}", "Error".bold().red(), err),
+            }
+        }
+        self.record_output(|recorded| recorded.extend(outputs));
+
+        println!(
+            " {} `{}` targets",
+            "Built".green().bold(),
+            self.sources.len() - self.colliding.len()
+        );
+    }
+
+    pub fn apply(&mut self, changes: &Changes) {
+        if changes.config {
+            match cli::get_config(&self.current_dir) {
+                Ok(config) => {
+                    self.config = config;
+                    self.build_all();
+                }
+                Err(err) => eprintln!("{}: {}", "Error".bold().red(), err),
+            }
+            return;
+        }
+
+        if changes.rescan {
+            self.build_all();
+            return;
+        }
+
+        for page in &changes.pages {
+            if page.is_file() {
+                self.sources.insert(page.clone());
+            } else if self.sources.remove(page) {
+                self.remove_output(page);
+            }
+        }
+
+        // An added page can collide with an existing one, and a removed page
+        // can leave the source it collided with free to build again
+        let colliding = self.find_collisions();
+        let resolved = self.colliding.difference(&colliding).cloned();
+        let changed = changes.pages.iter().filter(|page| page.is_file()).cloned();
+        let targets: BTreeSet<PathBuf> = changed
+            .chain(resolved)
+            .filter(|page| self.sources.contains(page) && !colliding.contains(page))
+            .collect();
+        self.colliding = colliding;
+
+        for page in &targets {
+            self.rebuild_page(page);
+        }
+    }
+
+    /// Reports sources that would be written to the same output, returning all
+    /// of them.
+    fn find_collisions(&self) -> BTreeSet<PathBuf> {
+        let content_dir = self.current_dir.join("content");
+        let sources: Vec<PathBuf> = self.sources.iter().cloned().collect();
+
+        let mut colliding = BTreeSet::new();
+        for collision in dist::find_output_collisions(&content_dir, &sources) {
+            eprintln!(
+                "{}: `{}` and `{}` would both be written to `{}`, skipping both",
+                "Error".bold().red(),
+                collision.first.display(),
+                collision.second.display(),
+                self.display_output(&collision.output).display()
+            );
+            colliding.insert(collision.first);
+            colliding.insert(collision.second);
+        }
+        colliding
+    }
+
+    fn rebuild_page(&self, target: &Path) {
+        match build::build_page(
+            &self.current_dir,
+            &self.dist_dir,
+            target,
+            &self.config,
+            self.options,
+        ) {
+            Ok(PageOutcome::Written(output, _, warnings)) => {
+                println!(
+                    " {} `{}`",
+                    "Rebuilt".green().bold(),
+                    self.display_output(&output).display()
+                );
+                for warning in &warnings {
+                    println!(
+                        "{}",
+                        build::format_warning(&self.current_dir, target, warning)
+                    );
+                }
+                self.record_output(|outputs| {
+                    outputs.insert(output);
+                });
+            }
+            Ok(PageOutcome::Draft) => self.remove_output(target),
+            Err(err) => eprintln!("{}: {}", "Error".bold().red(), err),
+        }
+    }
+
+    fn remove_output(&self, target: &Path) {
+        let output = dist::output_path(&self.current_dir.join("content"), target);
+        if dist::remove_output(&self.dist_dir, &output) {
+            println!(
+                " {} `{}`",
+                "Removed".yellow().bold(),
+                self.display_output(&output).display()
+            );
+        }
+        self.record_output(|outputs| {
+            outputs.remove(&output);
+        });
+    }
+
+    fn record_output(&self, update: impl FnOnce(&mut BTreeSet<PathBuf>)) {
+        if let Err(err) = dist::update_manifest(&self.dist_dir, update) {
+            eprintln!("{}: {}", "Error".bold().red(), err);
+        }
+    }
+
+    fn display_output(&self, output: &Path) -> PathBuf {
+        dist::display_path(&self.current_dir, &self.dist_dir.join(output)).to_path_buf()
+    }
+}
+
+/// Builds the site, then keeps rebuilding changed pages until interrupted.
+pub fn watch(current_dir: &Path, config: Config, poll: bool, verbose: bool) -> Result<(), Error> {
+    let options = BuildOptions {
+        verbose,
+        live_reload: false,
+    };
+
+    let watcher = SiteWatcher::new(current_dir, poll)?;
+    let mut rebuilder = Rebuilder::new(current_dir, &dist::dist_dir(current_dir), config, options);
+    rebuilder.build_all();
+
+    println!(
+        " {} for changes, press Ctrl+C to stop",
+        "Watching".green().bold()
+    );
+
+    while let Some(changes) = watcher.next_changes() {
+        rebuilder.apply(&changes);
+    }
+
+    Ok(())
+}